@@ -0,0 +1,41 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use interpreter::diagnostic::Diagnostics;
+use interpreter::interpreter::Interpreter;
+use interpreter::linter::Linter;
+use interpreter::parser::Parser;
+use interpreter::scanner::Scanner;
+use interpreter::typechecker::TypeChecker;
+
+// Every stage from scanning to the event loop, anything but a panic or a crash is fine
+fuzz_target!(|data: &[u8]| {
+    let source = match std::str::from_utf8(data) {
+        Ok(source) if source.len() <= 4096 => source,
+        _ => return,
+    };
+
+    let mut diagnostics = Diagnostics::default();
+
+    let tokens = Scanner::new(source).scan_tokens(&mut diagnostics).clone();
+    let statements = Parser::new(&tokens).parse(&mut diagnostics);
+
+    TypeChecker::new().check(&statements, &mut diagnostics);
+
+    let mut linter = Linter::new();
+    linter.lint_tokens(&tokens, &mut diagnostics);
+    linter.lint(&statements, &mut diagnostics);
+
+    if diagnostics.has_errors() {
+        return;
+    }
+
+    let mut interpreter = Interpreter::new();
+    interpreter.use_virtual_clock();
+    interpreter.set_fuel(Some(10_000));
+
+    let _ = interpreter
+        .interpret(&statements)
+        .and_then(|_| interpreter.run_event_loop());
+});
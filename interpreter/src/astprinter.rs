@@ -1,15 +1,55 @@
 use crate::expression::{Expr, Visitor};
 use crate::object::Object;
+use crate::statement::{self, Pattern, Stmt};
 use crate::token::Token;
 
-pub struct AstPrinter {}
+/// Prints expressions as Lisp style trees, `-123 * (45.67)` is `(* (- 123) (group 45.67))`.
+/// Statements work the same way, `print 1;` is `(print 1)`.
+pub struct AstPrinter;
 
 impl AstPrinter {
-    #[allow(dead_code)]
-    pub fn print(&mut self, expr: Expr) -> String {
+    pub fn print(&mut self, expr: &Expr) -> String {
         expr.accept(self)
     }
 
+    pub fn print_stmt(&mut self, stmt: &Stmt) -> String {
+        stmt.accept(self)
+    }
+
+    fn statements(&mut self, name: &str, statements: &[Stmt]) -> String {
+        let mut result = format!("({}", name);
+
+        for statement in statements {
+            result.push(' ');
+            result.push_str(&statement.accept(self));
+        }
+        result.push(')');
+
+        result
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) -> String {
+        match pattern {
+            Pattern::Name(name) => name.lexeme.clone(),
+            Pattern::List { elements, .. } => format!(
+                "[{}]",
+                elements
+                    .iter()
+                    .map(|element| self.pattern(element))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
+            Pattern::Fields { names, .. } => format!(
+                "{{{}}}",
+                names
+                    .iter()
+                    .map(|name| name.lexeme.clone())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
+        }
+    }
+
     fn parenthesize(&mut self, name: &str, exprs: &[&Expr]) -> String {
         let mut result = String::new();
 
@@ -26,49 +66,177 @@ impl AstPrinter {
 }
 
 impl Visitor<String> for AstPrinter {
-    #[allow(unused_variables)]
     fn visit_assign(&mut self, name: &Token, value: &Expr) -> String {
-        todo!();
+        format!("(= {} {})", name.lexeme, value.accept(self))
     }
     fn visit_binary(&mut self, left: &Expr, operator: &Token, right: &Expr) -> String {
         self.parenthesize(&operator.lexeme, &[left, right])
     }
-    #[allow(unused_variables)]
-    fn visit_call(&mut self, callee: &Token, paren: &Expr, arguments: &[Expr]) -> String {
-        todo!();
+    fn visit_call(&mut self, callee: &Expr, _paren: &Token, arguments: &[Expr]) -> String {
+        let mut exprs = vec![callee];
+        exprs.extend(arguments);
+
+        self.parenthesize("call", &exprs)
     }
-    #[allow(unused_variables)]
     fn visit_get(&mut self, object: &Expr, name: &Token) -> String {
-        todo!();
+        format!("(. {} {})", object.accept(self), name.lexeme)
     }
     fn visit_grouping(&mut self, expression: &Expr) -> String {
         self.parenthesize("group", &[expression])
     }
+    fn visit_list(&mut self, _bracket: &Token, elements: &[Expr]) -> String {
+        self.parenthesize("list", &elements.iter().collect::<Vec<_>>())
+    }
     fn visit_literal(&mut self, object: &Object) -> String {
-        object.to_string()
+        match object {
+            Object::String(string) => format!("{:?}", string),
+            object => object.to_string(),
+        }
     }
-    #[allow(unused_variables)]
     fn visit_logical(&mut self, left: &Expr, operator: &Token, right: &Expr) -> String {
-        todo!();
+        self.parenthesize(&operator.lexeme, &[left, right])
     }
-    #[allow(unused_variables)]
     fn visit_set(&mut self, object: &Expr, token: &Token, value: &Expr) -> String {
-        todo!();
+        format!(
+            "(= (. {} {}) {})",
+            object.accept(self),
+            token.lexeme,
+            value.accept(self)
+        )
     }
-    #[allow(unused_variables)]
-    fn visit_super(&mut self, keyword: &Token, method: &Token) -> String {
-        todo!();
+    fn visit_super(&mut self, _keyword: &Token, method: &Token) -> String {
+        format!("(super {})", method.lexeme)
     }
-    #[allow(unused_variables)]
-    fn visit_this(&mut self, keyword: &Token) -> String {
-        todo!();
+    fn visit_this(&mut self, _keyword: &Token) -> String {
+        "this".to_owned()
     }
     fn visit_unary(&mut self, operator: &Token, right: &Expr) -> String {
         self.parenthesize(&operator.lexeme, &[right])
     }
-    #[allow(unused_variables)]
     fn visit_variable(&mut self, name: &Token) -> String {
-        todo!();
+        name.lexeme.clone()
+    }
+}
+
+// `name: Type` when annotated
+fn typed(name: &Token, annotation: &Option<Token>) -> String {
+    match annotation {
+        Some(annotation) => format!("{}: {}", name.lexeme, annotation.lexeme),
+        None => name.lexeme.clone(),
+    }
+}
+
+impl statement::Visitor<String> for AstPrinter {
+    fn visit_assert(
+        &mut self,
+        _keyword: &Token,
+        condition: &Expr,
+        message: &Option<Expr>,
+        _source: &str,
+    ) -> String {
+        let mut exprs = vec![condition];
+        exprs.extend(message);
+
+        self.parenthesize("assert", &exprs)
+    }
+    fn visit_block(&mut self, statements: &[Stmt]) -> String {
+        self.statements("block", statements)
+    }
+    fn visit_destructure(&mut self, pattern: &Pattern, initializer: &Expr) -> String {
+        format!(
+            "(var {} {})",
+            self.pattern(pattern),
+            initializer.accept(self)
+        )
+    }
+    fn visit_expression(&mut self, value: &Expr) -> String {
+        value.accept(self)
+    }
+    fn visit_function(
+        &mut self,
+        name: &Token,
+        params: &[Token],
+        param_types: &[Option<Token>],
+        return_type: &Option<Token>,
+        body: &[Stmt],
+        _doc: &Option<String>,
+    ) -> String {
+        let params = params
+            .iter()
+            .zip(param_types)
+            .map(|(param, annotation)| typed(param, annotation))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let header = match return_type {
+            Some(return_type) => {
+                format!("fun {} ({}) -> {}", name.lexeme, params, return_type.lexeme)
+            }
+            None => format!("fun {} ({})", name.lexeme, params),
+        };
+
+        self.statements(&header, body)
+    }
+    fn visit_if(
+        &mut self,
+        condition: &Expr,
+        then_branch: &Stmt,
+        else_branch: &Option<Box<Stmt>>,
+    ) -> String {
+        let mut result = format!(
+            "(if {} {}",
+            condition.accept(self),
+            then_branch.accept(self)
+        );
+
+        if let Some(else_branch) = else_branch {
+            result.push(' ');
+            result.push_str(&else_branch.accept(self));
+        }
+        result.push(')');
+
+        result
+    }
+    fn visit_multi_assign(&mut self, targets: &[Token], values: &[Expr]) -> String {
+        let targets = targets
+            .iter()
+            .map(|target| target.lexeme.clone())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        self.parenthesize(
+            &format!("= ({})", targets),
+            &values.iter().collect::<Vec<_>>(),
+        )
+    }
+    fn visit_print(&mut self, value: &Expr) -> String {
+        self.parenthesize("print", &[value])
+    }
+    fn visit_return(&mut self, _keyword: &Token, value: &Option<Expr>) -> String {
+        self.parenthesize("return", &value.iter().collect::<Vec<_>>())
+    }
+    fn visit_spawn(
+        &mut self,
+        _keyword: &Token,
+        callee: &Expr,
+        paren: &Token,
+        arguments: &[Expr],
+    ) -> String {
+        format!("(spawn {})", self.visit_call(callee, paren, arguments))
+    }
+    fn visit_var(
+        &mut self,
+        name: &Token,
+        type_annotation: &Option<Token>,
+        initializer: &Option<Expr>,
+    ) -> String {
+        self.parenthesize(
+            &format!("var {}", typed(name, type_annotation)),
+            &initializer.iter().collect::<Vec<_>>(),
+        )
+    }
+    fn visit_while(&mut self, condition: &Expr, body: &Stmt) -> String {
+        format!("(while {} {})", condition.accept(self), body.accept(self))
     }
 }
 
@@ -76,8 +244,12 @@ impl Visitor<String> for AstPrinter {
 mod test {
     use super::AstPrinter;
 
+    use crate::diagnostic::Diagnostics;
     use crate::expression::Expr;
     use crate::object::Object;
+    use crate::parser::parse_source;
+    use crate::span::Span;
+    use crate::statement::Stmt;
     use crate::token::{Token, TokenType};
 
     #[test]
@@ -87,24 +259,72 @@ mod test {
                 operator: Token {
                     lexeme: "-".to_owned(),
                     line: 1,
+                    column: 1,
+                    offset: 0,
+                    length: 1,
+                    doc: None,
                     token_type: TokenType::Minus,
                 },
                 right: Box::new(Expr::Literal {
-                    object: Object::Number(123.0),
+                    object: Object::Int(123),
+                    span: Span::default(),
                 }),
             }),
             operator: Token {
                 lexeme: "*".to_owned(),
                 line: 1,
+                column: 6,
+                offset: 5,
+                length: 1,
+                doc: None,
                 token_type: TokenType::Star,
             },
             right: Box::new(Expr::Grouping {
                 expression: Box::new(Expr::Literal {
                     object: Object::Number(45.67),
+                    span: Span::default(),
                 }),
+                span: Span::default(),
             }),
         };
 
-        assert_eq!(AstPrinter {}.print(expression), "(* (- 123) (group 45.67))");
+        assert_eq!(AstPrinter.print(&expression), "(* (- 123) (group 45.67))");
+    }
+
+    #[test]
+    fn parsed_expressions() {
+        let mut diagnostics = Diagnostics::default();
+
+        let value = match &parse_source("a = f(1, \"x\") or [b, !d];", &mut diagnostics)[..] {
+            [Stmt::Expression { value }] => value.clone(),
+            statements => panic!("expected an expression, got {:?}", statements),
+        };
+
+        assert_eq!(
+            AstPrinter.print(&value),
+            "(= a (or (call f 1 \"x\") (list b (! d))))"
+        );
+    }
+
+    #[test]
+    fn statements() {
+        let source = "fun f(a: Int, b): Int { if (a) return b; else print nil; } \
+             var x = 1; var [y, [z]] = g(); x, y = y, x; while (x) { spawn f(1, 2); }";
+
+        let printed = parse_source(source, &mut Diagnostics::default())
+            .iter()
+            .map(|statement| AstPrinter.print_stmt(statement))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            printed,
+            vec![
+                "(fun f (a: Int b) -> Int (if a (return b) (print nil)))",
+                "(var x 1)",
+                "(var [y [z]] (call g))",
+                "(= (x y) y x)",
+                "(while x (block (spawn (call f 1 2))))",
+            ]
+        );
     }
 }
@@ -0,0 +1,252 @@
+// Stable error and warning codes. A code keeps its meaning forever: new kinds of errors get new
+// codes and codes that are no longer produced stay in the table so old output can still be
+// explained.
+
+#[derive(Debug)]
+pub struct ErrorCode {
+    pub code: &'static str,
+    pub title: &'static str,
+    pub explanation: &'static str,
+}
+
+macro_rules! error_codes {
+    ($($name:ident = $code:literal, $title:literal, $explanation:literal;)*) => {
+        $(
+            pub const $name: &ErrorCode = &ErrorCode {
+                code: $code,
+                title: $title,
+                explanation: $explanation,
+            };
+        )*
+
+        pub const ALL: &[&ErrorCode] = &[$($name),*];
+    };
+}
+
+error_codes! {
+    UNEXPECTED_CHARACTER = "E0001", "unexpected character",
+        "The scanner found a character that does not start any token, for example `@` or `$`
+outside of a string.
+
+    var price = $5;  // error
+    var price = 5;   // ok";
+
+    UNTERMINATED_STRING = "E0002", "unterminated string",
+        "A string literal was opened with `\"` but the file ended before the closing quote.
+Strings may span multiple lines, so the missing quote can be far from where the error is
+reported.
+
+    print \"hello;   // error
+    print \"hello\";  // ok";
+
+    UNTERMINATED_COMMENT = "E0003", "unterminated block comment",
+        "A `/*` comment was never closed. Block comments nest, every `/*` inside of one needs its
+own `*/`.
+
+    /* outer /* inner */     // error, outer is still open
+    /* outer /* inner */ */  // ok";
+
+    LITERAL_OUT_OF_RANGE = "E0004", "literal out of range",
+        "A number literal cannot be represented. Decimal literals (`1.5d`) hold up to 28
+significant digits.";
+
+    EXPECTED_TOKEN = "E0010", "expected token",
+        "The parser needed a specific token, like `;` after a statement or `)` after call
+arguments, and found something else. The message names the missing token.
+
+    print 1     // error: Expect ';' after value.
+    print 1;    // ok";
+
+    EXPECTED_EXPRESSION = "E0011", "expected expression",
+        "An expression was required here, but the next token cannot start one.
+
+    var a = ;     // error
+    var a = nil;  // ok";
+
+    INVALID_ASSIGNMENT_TARGET = "E0012", "invalid assignment target",
+        "The left side of `=` must be a variable (or a list of variables for multiple
+assignment). Values like literals or call results cannot be assigned to.
+
+    1 = a;       // error
+    f() = 2;     // error
+    a, b = 1, 2; // ok";
+
+    TOO_MANY_ARGUMENTS = "E0013", "too many arguments or parameters",
+        "Functions can have at most 255 parameters and calls can pass at most 255 arguments.";
+
+    SPAWN_WITHOUT_CALL = "E0014", "spawn without a call",
+        "`spawn` starts a task from a function call, it cannot run any other expression.
+
+    spawn worker;        // error
+    spawn worker(jobs);  // ok";
+
+    NESTING_TOO_DEEP = "E0015", "nesting too deep",
+        "Expressions, blocks and statements can be nested at most 100 levels deep, and chains of
+operators, calls or `else if` arms like `a + b + c` can have at most 2000 links. Longer code is
+almost certainly generated or broken, split it into functions or variables.";
+
+    TYPE_MISMATCH = "E0020", "mismatched types",
+        "A value of one type flows into a variable, parameter or return annotated with an
+incompatible type.
+
+    var count: Int = \"three\";  // error
+    var count: Int = 3;        // ok";
+
+    UNKNOWN_TYPE = "E0021", "unknown type",
+        "A type annotation names a type that does not exist. Available types are Any, Nil, Bool,
+Number, Int, Float, Decimal, String, List, Channel, Future and Function.";
+
+    MISSING_RETURN = "E0022", "missing return",
+        "A function annotated with a return type that does not accept nil can reach the end of
+its body without a `return`, where it would return nil.
+
+    fun sign(n): Int { if (n < 0) return -1; }                // error
+    fun sign(n): Int { if (n < 0) return -1; return 1; }      // ok";
+
+    UNDEFINED_VARIABLE = "E0030", "undefined variable",
+        "A variable was used or assigned before being declared with `var`, `fun` or a
+parameter.
+
+    print total;   // error
+    var total = 0;
+    print total;   // ok";
+
+    INVALID_OPERANDS = "E0031", "invalid operand types",
+        "An operator was applied to values it does not support: arithmetic needs numbers, `+`
+also accepts two strings, decimals do not mix with floats in arithmetic or ordering.
+
+    print 1 + \"a\";      // error
+    print 1.5d + 1.5;   // error
+    print 1.5d + 1;     // ok";
+
+    ARITY_MISMATCH = "E0032", "wrong number of arguments",
+        "A function was called with a different number of arguments than it declares
+parameters. The error points at the definition when it is known.
+
+    fun add(a, b) { return a + b; }
+    add(1);     // error
+    add(1, 2);  // ok";
+
+    NOT_CALLABLE = "E0033", "value is not callable",
+        "Only functions can be called.
+
+    var name = \"x\";
+    name();  // error";
+
+    DIVISION_BY_ZERO = "E0034", "division by zero",
+        "Integers and decimals cannot be divided by zero. Floats follow IEEE 754 and produce
+infinity or NaN instead.";
+
+    NUMERIC_OVERFLOW = "E0035", "numeric overflow",
+        "The result of decimal arithmetic does not fit into 96 bits, even with every place after
+the point rounded off. Integer arithmetic never overflows, integers are promoted to arbitrary
+precision instead.";
+
+    DESTRUCTURING_MISMATCH = "E0036", "destructuring mismatch",
+        "The value on the right side of a destructuring declaration or multiple assignment does
+not have the shape of the left side: it is not a list or has a different number of elements.
+
+    var [a, b] = [1, 2, 3];  // error
+    var [a, b] = [1, 2];     // ok";
+
+    ASSERTION_FAILED = "E0037", "assertion failed",
+        "An `assert` statement evaluated to a falsy value. The message shows the condition as
+written and both sides of comparisons. Assertions can be disabled with `--no-asserts`.";
+
+    DEADLOCK = "E0038", "deadlock",
+        "Every task, including the main script, is waiting on a channel or future that nothing
+will ever complete.
+
+    var ch = channel();
+    recv(ch);  // error, nobody sends to ch";
+
+    NATIVE_ERROR = "E0039", "built-in function failed",
+        "A built-in function rejected its arguments or failed to do its job, for example
+`readFile` on a missing file. The message describes what went wrong.";
+
+    STACK_OVERFLOW = "E0040", "stack overflow",
+        "Calls were nested more than 500 levels deep, or fewer calls with big bodies used up the
+stack. Usually this is recursion without a base case.
+
+    fun count(n) { return count(n + 1); }  // error
+    fun count(n) { if (n > 10) return n; return count(n + 1); }  // ok";
+
+    UNSUPPORTED = "E0041", "unsupported feature",
+        "The code uses a part of the language this interpreter does not implement yet, like
+classes.";
+
+    DECIMAL_CONVERSION = "E0042", "integer too large for a decimal",
+        "Arithmetic with a decimal and an integer turns the integer into a decimal first, which
+only works for integers that fit into 96 bits.
+
+    var big = 100000000000000000000000000000000;
+    print big * 1.5d;  // error";
+
+    UNUSED_VARIABLE = "W0001", "unused variable",
+        "A local variable or parameter is never read. Assigning to it does not count as a use.
+Names starting with an underscore are never reported. Disable with `--allow=unused-variable`.
+
+    fun area(width, height) { return width * width; }  // warning: height
+    fun area(width, _height) { return width * width; } // ok";
+
+    UNREACHABLE_CODE = "W0002", "unreachable code",
+        "Statements after a `return` in the same block can never run. Disable with
+`--allow=unreachable-code`.
+
+    fun f() {
+        return 1;
+        print \"done\";  // warning
+    }";
+
+    CONSTANT_CONDITION = "W0003", "constant condition",
+        "The condition of an `if` is a literal, so the same branch is taken every time. Disable
+with `--allow=constant-condition`.
+
+    if (true) print 1;  // warning";
+
+    SHADOWED_VARIABLE = "W0004", "shadowed variable",
+        "A local variable or parameter has the same name as a variable from an enclosing scope,
+which becomes unreachable until the inner scope ends. Disable with `--allow=shadowing`.
+
+    var count = 0;
+    fun f(count) { print count; }  // warning";
+
+    STRAY_DOC_COMMENT = "W0005", "stray doc comment",
+        "A `///` doc comment is not followed by a function declaration. Only functions keep their
+documentation for `help`, anywhere else it is dropped. Use `//` for regular comments. Disable with
+`--allow=stray-doc`.
+
+    /// The answer.
+    var answer = 42;  // warning
+    /// Adds two numbers.
+    fun add(a, b) { return a + b; }  // ok";
+}
+
+pub fn explain(code: &str) -> Option<&'static ErrorCode> {
+    ALL.iter()
+        .copied()
+        .find(|error_code| error_code.code.eq_ignore_ascii_case(code))
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::{explain, ALL, INVALID_ASSIGNMENT_TARGET};
+
+    #[test]
+    fn codes_are_unique() {
+        let unique = ALL.iter().map(|code| code.code).collect::<HashSet<_>>();
+
+        assert_eq!(unique.len(), ALL.len());
+    }
+
+    #[test]
+    fn explain_lookup() {
+        assert_eq!(
+            explain("e0012").map(|code| code.title),
+            Some(INVALID_ASSIGNMENT_TARGET.title)
+        );
+        assert!(explain("E9999").is_none());
+    }
+}
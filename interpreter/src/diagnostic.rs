@@ -0,0 +1,264 @@
+use std::fmt;
+
+use crate::codes::ErrorCode;
+use crate::span::{self, Label, Span};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// Something worth telling the user about the program, produced by any stage from scanning to
+/// running it
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Option<&'static str>,
+    // None for problems with the program as a whole, like a deadlock
+    pub span: Option<Span>,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(span: Option<Span>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            code: None,
+            span,
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn warning(span: Option<Span>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(span, message)
+        }
+    }
+
+    pub fn with_code(self, code: &'static ErrorCode) -> Self {
+        Self {
+            code: Some(code.code),
+            ..self
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Human readable form with the relevant lines of `source`, read from `file` if it is known
+    pub fn render(&self, source: &str, file: Option<&str>) -> String {
+        let header = match self.code {
+            Some(code) => format!("{}[{}]", self.severity, code),
+            None => self.severity.to_string(),
+        };
+
+        let mut out = match self.span {
+            Some(span) => span::render(source, file, &header, &self.message, span, &self.labels),
+            // nothing to point at, the file still says where it happened
+            None => match file {
+                Some(file) => format!("{}: {}\n --> {}", header, self.message, file),
+                None => format!("{}: {}", header, self.message),
+            },
+        };
+
+        for note in &self.notes {
+            out.push_str(&format!("\n  = note: {}", note));
+        }
+
+        out
+    }
+
+    /// Single line JSON object for tools, `file` is None for code that did not come from a file
+    pub fn to_json(&self, file: Option<&str>) -> String {
+        let span = |span: Option<Span>| match span {
+            Some(span) => format!(
+                "\"line\":{},\"column\":{},\"span\":{{\"offset\":{},\"length\":{}}}",
+                span.line, span.column, span.offset, span.length
+            ),
+            None => "\"line\":null,\"column\":null,\"span\":null".to_owned(),
+        };
+
+        let labels = self
+            .labels
+            .iter()
+            .map(|label| {
+                format!(
+                    "{{{},\"message\":{}}}",
+                    span(Some(label.span)),
+                    json_string(&label.message)
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let notes = self
+            .notes
+            .iter()
+            .map(|note| json_string(note))
+            .collect::<Vec<_>>();
+
+        format!(
+            "{{\"file\":{},{},\"severity\":\"{}\",\"code\":{},\"message\":{},\"labels\":[{}],\"notes\":[{}]}}",
+            file.map_or("null".to_owned(), json_string),
+            span(self.span),
+            self.severity,
+            self.code.map_or("null".to_owned(), json_string),
+            json_string(&self.message),
+            labels.join(","),
+            notes.join(",")
+        )
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+
+    out.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+
+    out
+}
+
+/// Collects diagnostics from the scanner, parser, checker and interpreter. Whoever runs those
+/// owns the sink and decides what to do with its contents.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    items: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.items.push(diagnostic);
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.items.iter().any(Diagnostic::is_error)
+    }
+
+    pub fn drain(&mut self) -> impl Iterator<Item = Diagnostic> + '_ {
+        self.items.drain(..)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Diagnostic, Diagnostics, Severity};
+
+    use crate::parser::parse_source;
+    use crate::span::{Label, Span};
+
+    #[test]
+    fn scanner_and_parser_report_into_sink() {
+        let mut diagnostics = Diagnostics::default();
+        parse_source("var a = @;\nprint 1", &mut diagnostics);
+
+        assert!(diagnostics.has_errors());
+
+        let collected = diagnostics
+            .drain()
+            .map(|diagnostic| {
+                let span = diagnostic.span.expect("syntax errors have a location");
+
+                (
+                    diagnostic.severity,
+                    span.line,
+                    span.column,
+                    diagnostic.message,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            collected[0],
+            (Severity::Error, 1, 9, "Unexpected character: @".to_owned())
+        );
+        assert_eq!(
+            collected.last().unwrap(),
+            &(Severity::Error, 2, 8, "Expect ';' after value.".to_owned())
+        );
+    }
+
+    #[test]
+    fn render_notes_and_code() {
+        let diagnostic = Diagnostic {
+            code: Some("E0001"),
+            notes: vec!["in f, called at 1:2".to_owned()],
+            ..Diagnostic::error(None, "Deadlock.")
+        };
+
+        assert_eq!(
+            diagnostic.render("", None),
+            "error[E0001]: Deadlock.\n  = note: in f, called at 1:2"
+        );
+        assert_eq!(
+            diagnostic.render("", Some("tool.lox")),
+            "error[E0001]: Deadlock.\n --> tool.lox\n  = note: in f, called at 1:2"
+        );
+    }
+
+    #[test]
+    fn json_line() {
+        let diagnostic = Diagnostic {
+            labels: vec![Label::new(
+                Span {
+                    offset: 0,
+                    length: 3,
+                    line: 1,
+                    column: 1,
+                },
+                "defined here",
+            )],
+            ..Diagnostic::error(
+                Some(Span {
+                    offset: 10,
+                    length: 1,
+                    line: 2,
+                    column: 3,
+                }),
+                "Unexpected \"x\"\n",
+            )
+        };
+
+        assert_eq!(
+            diagnostic.to_json(Some("dir\\a.lox")),
+            concat!(
+                r#"{"file":"dir\\a.lox","line":2,"column":3,"span":{"offset":10,"length":1},"#,
+                r#""severity":"error","code":null,"message":"Unexpected \"x\"\n","#,
+                r#""labels":[{"line":1,"column":1,"span":{"offset":0,"length":3},"#,
+                r#""message":"defined here"}],"notes":[]}"#
+            )
+        );
+        assert_eq!(
+            Diagnostic::error(None, "Deadlock.").to_json(None),
+            r#"{"file":null,"line":null,"column":null,"span":null,"severity":"error","code":null,"message":"Deadlock.","labels":[],"notes":[]}"#
+        );
+    }
+}
@@ -3,8 +3,11 @@ use std::collections::HashMap;
 use std::convert::{From, Into};
 use std::rc::Rc;
 
+use crate::codes;
 use crate::error::LoxError;
 use crate::object::Object;
+use crate::scanner::KEYWORDS;
+use crate::suggestion;
 use crate::token::Token;
 
 pub type SharedEnv = Rc<RefCell<Environment>>;
@@ -29,36 +32,86 @@ impl Environment {
     }
 
     pub fn get(&self, name: &Token) -> Result<Object, LoxError> {
-        match self.values.get(&name.lexeme) {
-            Some(value) => Ok(value.clone()),
-            None => {
-                if let Some(enclosing) = &self.enclosing {
-                    return enclosing.borrow().get(name);
-                }
-
-                return Err(LoxError::Runtime {
-                    token: name.clone(),
-                    message: format!("Undefined variable '{}'.", name.lexeme),
-                });
-            }
+        self.find(&name.lexeme)
+            .ok_or_else(|| self.undefined_variable(name))
+    }
+
+    fn find(&self, name: &str) -> Option<Object> {
+        match self.values.get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.enclosing.as_ref()?.borrow().find(name),
         }
     }
 
     pub fn assign(&mut self, name: &Token, value: &Object) -> Result<(), LoxError> {
-        if self.values.contains_key(&name.lexeme) {
-            self.values.insert(name.lexeme.clone(), value.clone());
+        if self.try_assign(&name.lexeme, value) {
+            Ok(())
+        } else {
+            Err(self.undefined_variable(name))
+        }
+    }
+
+    fn try_assign(&mut self, name: &str, value: &Object) -> bool {
+        if let Some(slot) = self.values.get_mut(name) {
+            *slot = value.clone();
 
-            return Ok(());
+            return true;
         }
 
-        if let Some(enclosing) = &mut self.enclosing {
-            return enclosing.borrow_mut().assign(name, value);
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().try_assign(name, value),
+            None => false,
+        }
+    }
+
+    // every name visible from here, inner scopes first
+    pub fn names(&self) -> Vec<String> {
+        let mut names = self.values.keys().cloned().collect::<Vec<_>>();
+
+        if let Some(enclosing) = &self.enclosing {
+            names.extend(enclosing.borrow().names());
         }
 
-        return Err(LoxError::Runtime {
-            token: name.clone(),
+        names
+    }
+
+    // every visible name with its value, a name shadowed by an inner scope is left out
+    pub fn bindings(&self) -> Vec<(String, Object)> {
+        let mut bindings = match &self.enclosing {
+            Some(enclosing) => enclosing.borrow().bindings(),
+            None => Vec::new(),
+        };
+
+        bindings.retain(|(name, _)| !self.values.contains_key(name));
+        bindings.extend(
+            self.values
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone())),
+        );
+
+        bindings
+    }
+
+    fn undefined_variable(&self, name: &Token) -> LoxError {
+        let names = self.names();
+
+        LoxError::Runtime {
+            token: Box::new(name.clone()),
+            code: codes::UNDEFINED_VARIABLE,
             message: format!("Undefined variable '{}'.", name.lexeme),
-        });
+            labels: Vec::new(),
+            // a misspelled keyword reads as a variable, as in `pritn(x);`
+            notes: suggestion::similar_name(
+                &name.lexeme,
+                names
+                    .iter()
+                    .map(String::as_str)
+                    .chain(KEYWORDS.keys().copied()),
+            )
+            .map(|similar| format!("did you mean '{}'?", similar))
+            .into_iter()
+            .collect(),
+        }
     }
 
     pub fn define(&mut self, name: &str, value: &Object) {
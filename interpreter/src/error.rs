@@ -1,19 +1,63 @@
 use thiserror::Error;
 
+use crate::codes::ErrorCode;
 use crate::object::Object;
+use crate::span::Label;
 use crate::token::Token;
 
 #[derive(Debug, Error)]
 pub enum LoxError {
-    #[error("Error")]
+    #[error("{0}")]
     Error(String),
 
     #[error("RuntimeError")]
-    Runtime { token: Token, message: String },
+    Runtime {
+        token: Box<Token>,
+        code: &'static ErrorCode,
+        message: String,
+        // secondary spans pointing at related code
+        labels: Vec<Label>,
+        // extra hints shown after the code, like a suggestion for a misspelled name
+        notes: Vec<String>,
+    },
+
+    // every task is blocked, raised without a location when the program ends
+    #[error("{0}")]
+    Deadlock(String),
 
     #[error("Function return")]
-    Return(Object),
+    Return(Box<Object>),
+
+    // `exit(code)` unwinds everything up to whoever runs the program
+    #[error("Exit with code {0}")]
+    Exit(i32),
 
     #[error("ParserError")]
     Parser,
 }
+
+impl LoxError {
+    // only runtime errors can point at more code
+    pub fn with_labels(self, extra: impl IntoIterator<Item = Label>) -> Self {
+        match self {
+            LoxError::Runtime {
+                token,
+                code,
+                message,
+                mut labels,
+                notes,
+            } => {
+                labels.extend(extra);
+
+                LoxError::Runtime {
+                    token,
+                    code,
+                    message,
+                    labels,
+                    notes,
+                }
+            }
+            err => err,
+        }
+    }
+}
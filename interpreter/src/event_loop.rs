@@ -0,0 +1,355 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::LoxError;
+use crate::function::Function;
+use crate::interpreter::Interpreter;
+use crate::object::Object;
+use crate::scheduler::TaskId;
+
+type IoResult = Result<String, String>;
+
+pub type SharedFuture = Rc<RefCell<Future>>;
+
+#[derive(Debug)]
+pub enum FutureState {
+    Pending,
+    Resolved(Object),
+    Rejected(String),
+}
+
+// Result of an operation that completes later, handed out by natives like `sleep` and `readFile`
+#[derive(Debug)]
+pub struct Future {
+    pub state: FutureState,
+    // tasks suspended in await() on this future
+    waiters: Vec<TaskId>,
+}
+
+impl Future {
+    pub fn pending() -> SharedFuture {
+        Rc::new(RefCell::new(Self {
+            state: FutureState::Pending,
+            waiters: Vec::new(),
+        }))
+    }
+}
+
+pub enum Clock {
+    Real(Instant),
+    // only moves when the loop jumps to the next timer, keeps runs reproducible
+    Virtual(u64),
+}
+
+impl Clock {
+    // milliseconds since the interpreter started
+    fn now(&self) -> u64 {
+        match self {
+            Clock::Real(start) => start.elapsed().as_millis() as u64,
+            Clock::Virtual(now) => *now,
+        }
+    }
+}
+
+enum TimerAction {
+    Call(Function),
+    Resolve(SharedFuture),
+}
+
+struct Timer {
+    action: TimerAction,
+    interval: Option<u64>,
+}
+
+pub struct EventLoop {
+    clock: Clock,
+    next_id: usize,
+    // ordered by due time, ties fire in creation order
+    timers: BTreeMap<(u64, usize), Timer>,
+    pending_io: HashMap<usize, SharedFuture>,
+    io_sender: Sender<(usize, IoResult)>,
+    io_receiver: Receiver<(usize, IoResult)>,
+}
+
+impl Default for EventLoop {
+    fn default() -> Self {
+        let (io_sender, io_receiver) = mpsc::channel();
+
+        Self {
+            clock: Clock::Real(Instant::now()),
+            next_id: 0,
+            timers: BTreeMap::new(),
+            pending_io: HashMap::new(),
+            io_sender,
+            io_receiver,
+        }
+    }
+}
+
+impl EventLoop {
+    pub fn use_virtual_clock(&mut self) {
+        self.clock = Clock::Virtual(0);
+    }
+
+    pub fn virtual_time(&self) -> Option<u64> {
+        match self.clock {
+            Clock::Real(_) => None,
+            Clock::Virtual(now) => Some(now),
+        }
+    }
+
+    fn next_id(&mut self) -> usize {
+        self.next_id += 1;
+
+        self.next_id
+    }
+
+    fn add_timer(&mut self, delay: u64, action: TimerAction, interval: Option<u64>) -> usize {
+        let id = self.next_id();
+
+        // delays too long to ever happen just never fire
+        self.timers.insert(
+            (self.clock.now().saturating_add(delay), id),
+            Timer { action, interval },
+        );
+
+        id
+    }
+
+    pub fn set_timeout(&mut self, callback: Function, delay: u64) -> usize {
+        self.add_timer(delay, TimerAction::Call(callback), None)
+    }
+
+    pub fn set_interval(&mut self, callback: Function, interval: u64) -> usize {
+        self.add_timer(interval, TimerAction::Call(callback), Some(interval))
+    }
+
+    pub fn clear_timer(&mut self, id: usize) {
+        self.timers.retain(|&(_, timer_id), _| timer_id != id);
+    }
+
+    pub fn sleep(&mut self, delay: u64) -> SharedFuture {
+        let future = Future::pending();
+
+        self.add_timer(delay, TimerAction::Resolve(Rc::clone(&future)), None);
+
+        future
+    }
+
+    // Runs `work` off the interpreter and returns a future for its result. With the virtual
+    // clock the work runs right away instead, the future still resolves on a later loop turn.
+    pub fn spawn_io(&mut self, work: impl FnOnce() -> IoResult + Send + 'static) -> SharedFuture {
+        let id = self.next_id();
+        let future = Future::pending();
+        let sender = self.io_sender.clone();
+
+        self.pending_io.insert(id, Rc::clone(&future));
+
+        match self.clock {
+            Clock::Real(_) => {
+                thread::spawn(move || {
+                    let _ = sender.send((id, work()));
+                });
+            }
+            Clock::Virtual(_) => {
+                let _ = sender.send((id, work()));
+            }
+        }
+
+        future
+    }
+
+    fn is_idle(&self) -> bool {
+        self.timers.is_empty() && self.pending_io.is_empty()
+    }
+
+    // Blocks until the next timer is due or I/O completes
+    fn wait(&mut self) -> Wakeup {
+        if let Ok((id, result)) = self.io_receiver.try_recv() {
+            return Wakeup::Io(id, result);
+        }
+
+        if self.is_idle() {
+            return Wakeup::Idle;
+        }
+
+        let next_due = self.timers.keys().next().map(|&(due, _)| due);
+
+        match (&mut self.clock, next_due) {
+            (Clock::Virtual(now), Some(due)) if self.pending_io.is_empty() => {
+                *now = (*now).max(due);
+            }
+            (clock, next_due) => {
+                let timeout =
+                    next_due.map(|due| Duration::from_millis(due.saturating_sub(clock.now())));
+
+                if self.pending_io.is_empty() {
+                    thread::sleep(timeout.unwrap_or_default());
+                } else {
+                    let received = match timeout {
+                        Some(timeout) => self.io_receiver.recv_timeout(timeout),
+                        None => self
+                            .io_receiver
+                            .recv()
+                            .map_err(|_| RecvTimeoutError::Disconnected),
+                    };
+
+                    if let Ok((id, result)) = received {
+                        return Wakeup::Io(id, result);
+                    }
+                }
+            }
+        }
+
+        Wakeup::TimersDue
+    }
+
+    // the earliest timer if it is due by `now`
+    fn pop_due_timer(&mut self, now: u64) -> Option<((u64, usize), Timer)> {
+        let entry = self.timers.first_entry()?;
+
+        if entry.key().0 > now {
+            return None;
+        }
+
+        Some(entry.remove_entry())
+    }
+}
+
+enum Wakeup {
+    // nothing is left to wait for
+    Idle,
+    Io(usize, IoResult),
+    TimersDue,
+}
+
+// The event loop is never borrowed while Lox code runs, timer callbacks and woken tasks use it too
+impl Interpreter {
+    fn resolve(&mut self, future: &SharedFuture, state: FutureState) {
+        let mut future = future.borrow_mut();
+
+        future.state = state;
+
+        self.scheduler().wake(future.waiters.drain(..));
+    }
+
+    fn complete_io(&mut self, id: usize, result: IoResult) {
+        let future = self.event_loop().pending_io.remove(&id);
+
+        if let Some(future) = future {
+            let state = match result {
+                Ok(value) => FutureState::Resolved(Object::String(value)),
+                Err(message) => FutureState::Rejected(message),
+            };
+
+            self.resolve(&future, state);
+        }
+    }
+
+    // Waits for the next timer or I/O completion and handles it, returns false once nothing
+    // is left to wait for
+    fn event_loop_turn(&mut self) -> Result<bool, LoxError> {
+        let wakeup = self.event_loop().wait();
+
+        match wakeup {
+            Wakeup::Idle => return Ok(false),
+            Wakeup::Io(id, result) => self.complete_io(id, result),
+            Wakeup::TimersDue => self.fire_timers()?,
+        }
+
+        Ok(true)
+    }
+
+    fn fire_timers(&mut self) -> Result<(), LoxError> {
+        let now = self.event_loop().clock.now();
+
+        loop {
+            let due = self.event_loop().pop_due_timer(now);
+
+            let ((due, id), timer) = match due {
+                Some(timer) => timer,
+                None => return Ok(()),
+            };
+
+            match &timer.action {
+                // callbacks run as tasks so they are free to block
+                TimerAction::Call(callback) => self.spawn(callback.clone(), Vec::new(), None)?,
+                TimerAction::Resolve(future) => {
+                    self.resolve(future, FutureState::Resolved(Object::Null))
+                }
+            }
+
+            // at least a millisecond apart so a zero interval cannot starve the loop, an interval
+            // that would come after the end of time is over
+            let next = timer
+                .interval
+                .and_then(|interval| due.checked_add(interval.max(1)));
+
+            if let Some(next) = next {
+                self.event_loop().timers.insert((next, id), timer);
+            }
+        }
+    }
+
+    // runs tasks and the event loop until `done` holds, returns false if that can never happen
+    pub(crate) fn run_until(
+        &mut self,
+        done: impl Fn(&Interpreter) -> bool,
+    ) -> Result<bool, LoxError> {
+        loop {
+            if self.run_tasks_until(&done)? {
+                return Ok(true);
+            }
+
+            if !self.event_loop_turn()? {
+                return Ok(false);
+            }
+        }
+    }
+
+    // called once the main script is done
+    pub fn run_event_loop(&mut self) -> Result<(), LoxError> {
+        let result = self
+            .run_until(|_| false)
+            .and_then(|_| self.check_deadlock());
+
+        if result.is_err() {
+            self.reset_tasks();
+        }
+
+        result
+    }
+
+    pub(crate) fn await_future(&mut self, future: &SharedFuture) -> Result<Object, LoxError> {
+        loop {
+            match &future.borrow().state {
+                FutureState::Pending => {}
+                FutureState::Resolved(value) => return Ok(value.clone()),
+                FutureState::Rejected(message) => return Err(LoxError::Error(message.clone())),
+            }
+
+            match &self.task {
+                Some(task) => {
+                    future.borrow_mut().waiters.push(task.id);
+
+                    task.suspend();
+                }
+                None => {
+                    let resolved =
+                        |_: &Interpreter| !matches!(future.borrow().state, FutureState::Pending);
+
+                    if !self.run_until(resolved)? {
+                        return Err(LoxError::Deadlock(
+                            "Deadlock: awaiting a future that can never complete.".to_owned(),
+                        ));
+                    }
+                }
+            }
+        }
+    }
+}
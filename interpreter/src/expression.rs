@@ -1,4 +1,5 @@
 use crate::object::Object;
+use crate::span::Span;
 use crate::token::Token;
 
 #[derive(Clone, Debug)]
@@ -23,9 +24,16 @@ pub enum Expr {
     },
     Grouping {
         expression: Box<Expr>,
+        // including the parentheses
+        span: Span,
+    },
+    List {
+        bracket: Token,
+        elements: Vec<Expr>,
     },
     Literal {
         object: Object,
+        span: Span,
     },
     Logical {
         left: Box<Expr>,
@@ -54,6 +62,27 @@ pub enum Expr {
 }
 
 impl Expr {
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Assign { name, value } => name.span().to(value.span()),
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                left.span().to(right.span())
+            }
+            Expr::Call { callee, paren, .. } => callee.span().to(paren.span()),
+            Expr::Get { object, name } => object.span().to(name.span()),
+            Expr::Grouping { span, .. } | Expr::Literal { span, .. } => *span,
+            Expr::List { bracket, elements } => match elements.last() {
+                Some(last) => bracket.span().to(last.span()),
+                None => bracket.span(),
+            },
+            Expr::Set { object, value, .. } => object.span().to(value.span()),
+            Expr::Super { keyword, method } => keyword.span().to(method.span()),
+            Expr::This { keyword } => keyword.span(),
+            Expr::Unary { operator, right } => operator.span().to(right.span()),
+            Expr::Variable { name } => name.span(),
+        }
+    }
+
     pub fn accept<R>(&self, visitor: &mut dyn Visitor<R>) -> R {
         match self {
             Expr::Assign { name, value } => visitor.visit_assign(name, value),
@@ -68,8 +97,9 @@ impl Expr {
                 arguments,
             } => visitor.visit_call(callee, paren, arguments),
             Expr::Get { object, name } => visitor.visit_get(object, name),
-            Expr::Grouping { expression } => visitor.visit_grouping(expression),
-            Expr::Literal { object } => visitor.visit_literal(object),
+            Expr::Grouping { expression, .. } => visitor.visit_grouping(expression),
+            Expr::List { bracket, elements } => visitor.visit_list(bracket, elements),
+            Expr::Literal { object, .. } => visitor.visit_literal(object),
             Expr::Logical {
                 left,
                 operator,
@@ -93,6 +123,7 @@ pub trait Visitor<R> {
     fn visit_call(&mut self, callee: &Expr, paren: &Token, arguments: &[Expr]) -> R;
     fn visit_get(&mut self, object: &Expr, name: &Token) -> R;
     fn visit_grouping(&mut self, expression: &Expr) -> R;
+    fn visit_list(&mut self, bracket: &Token, elements: &[Expr]) -> R;
     fn visit_literal(&mut self, object: &Object) -> R;
     fn visit_logical(&mut self, left: &Expr, operator: &Token, right: &Expr) -> R;
     fn visit_set(&mut self, object: &Expr, token: &Token, value: &Expr) -> R;
@@ -0,0 +1,417 @@
+use crate::token::{Token, TokenType};
+
+const INDENT: &str = "  ";
+
+/// Lays out already parsed source in one style: one statement per line, blocks indented by two
+/// spaces and single spaces around operators. Comments and line breaks inside a statement are
+/// kept, runs of blank lines become one.
+pub fn format(source: &str, tokens: &[Token]) -> String {
+    let mut formatter = Formatter {
+        source,
+        out: String::new(),
+        indent: 0,
+        line_indent: 0,
+        brackets: Vec::new(),
+        braces: Vec::new(),
+        // the first token starts a statement
+        line_break: true,
+        unary: false,
+    };
+
+    // the scanner skips a `#!` line, it stays as it is
+    let code_start = match source.strip_prefix("#!") {
+        Some(_) => source.find('\n').unwrap_or(source.len()),
+        None => 0,
+    };
+
+    formatter.out.push_str(&source[..code_start]);
+
+    let mut previous: Option<&Token> = None;
+
+    for token in tokens {
+        let start = previous.map_or(code_start, |previous| previous.offset + previous.length);
+        let gap = &source[start..token.offset.max(start)];
+
+        formatter.token(previous, token, gap);
+
+        previous = Some(token);
+    }
+
+    formatter.out.truncate(formatter.out.trim_end().len());
+
+    if !formatter.out.is_empty() {
+        formatter.out.push('\n');
+    }
+
+    formatter.out
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    out: String,
+    // of blocks
+    indent: usize,
+    // indentation of the line being written
+    line_indent: usize,
+    // open parentheses and brackets, with the indentation of the line each one opened on
+    brackets: Vec<usize>,
+    // open braces, true for blocks and false for `var {x, y}` patterns
+    braces: Vec<bool>,
+    // a statement or block ended, the next token goes on a new line
+    line_break: bool,
+    // the last token was a `-` or `!` applied to what follows
+    unary: bool,
+}
+
+// a comment in the whitespace between two tokens
+struct Comment<'a> {
+    // line breaks before it
+    newlines: usize,
+    text: &'a str,
+}
+
+impl Formatter<'_> {
+    fn token(&mut self, previous: Option<&Token>, token: &Token, gap: &str) {
+        let (comments, mut newlines) = comments(gap);
+        // a `//` comment runs until the end of its line
+        let mut must_break = false;
+
+        for comment in comments {
+            if comment.newlines == 0 && !self.out.is_empty() {
+                self.out.push(' ');
+            } else {
+                let indent = self.next_indent(false);
+                self.new_line(comment.newlines, previous, false, indent);
+            }
+
+            self.out.push_str(comment.text);
+
+            must_break = comment.text.starts_with("//");
+        }
+
+        if must_break {
+            newlines = newlines.max(1);
+        }
+
+        let token_type = &token.token_type;
+
+        if *token_type == TokenType::Eof {
+            return;
+        }
+
+        let closes_block =
+            *token_type == TokenType::RightBrace && self.braces.last() == Some(&true);
+
+        if closes_block {
+            self.braces.pop();
+            self.indent = self.indent.saturating_sub(1);
+        }
+
+        let empty_block = closes_block
+            && previous.map(|previous| &previous.token_type) == Some(&TokenType::LeftBrace)
+            && newlines == 0;
+
+        // `} else {` stays on one line unless a comment is in the way
+        let else_after_block = *token_type == TokenType::Else
+            && previous.map(|previous| &previous.token_type) == Some(&TokenType::RightBrace)
+            && !must_break;
+
+        if else_after_block {
+            newlines = 0;
+        }
+
+        let statement_start =
+            (self.line_break || closes_block) && !else_after_block && !empty_block;
+
+        if !self.out.is_empty() && (statement_start || newlines > 0) {
+            let indent = self.next_indent(statement_start);
+            let closes_bracket =
+                matches!(token_type, TokenType::RightParen | TokenType::RightBracket);
+
+            let indent = match self.brackets.last() {
+                Some(&opened_at) if closes_bracket && !statement_start => opened_at,
+                _ => indent,
+            };
+
+            self.new_line(newlines, previous, closes_block, indent);
+        } else if let Some(previous) = previous {
+            let in_pattern = self.braces.last() == Some(&false);
+
+            if !empty_block && needs_space(previous, token, in_pattern, self.unary) {
+                self.out.push(' ');
+            }
+        }
+
+        self.out
+            .push_str(&self.source[token.offset..token.offset + token.length]);
+
+        self.line_break = false;
+        self.unary = match token_type {
+            TokenType::Minus => !previous.is_some_and(ends_operand),
+            TokenType::Bang => true,
+            _ => false,
+        };
+
+        match token_type {
+            TokenType::LeftBrace => {
+                let block = previous.map(|previous| &previous.token_type) != Some(&TokenType::Var);
+
+                self.braces.push(block);
+
+                if block {
+                    self.indent += 1;
+                    self.line_break = true;
+                }
+            }
+            TokenType::RightBrace if closes_block => self.line_break = true,
+            TokenType::RightBrace => {
+                self.braces.pop();
+            }
+            TokenType::LeftParen | TokenType::LeftBracket => self.brackets.push(self.line_indent),
+            TokenType::RightParen | TokenType::RightBracket => {
+                self.brackets.pop();
+            }
+            TokenType::Semicolon => self.line_break = self.brackets.is_empty(),
+            _ => {}
+        }
+    }
+
+    // a statement starts at the block's indentation, a line continuing one is indented further
+    fn next_indent(&self, statement_start: bool) -> usize {
+        if statement_start || self.line_break {
+            return self.indent;
+        }
+
+        match self.brackets.last() {
+            Some(opened_at) => opened_at + 1,
+            None => self.indent + 1,
+        }
+    }
+
+    fn new_line(
+        &mut self,
+        newlines: usize,
+        previous: Option<&Token>,
+        closes_block: bool,
+        indent: usize,
+    ) {
+        if self.out.is_empty() {
+            self.line_indent = indent;
+            self.out.push_str(&INDENT.repeat(indent));
+
+            return;
+        }
+
+        let after_open_brace =
+            previous.map(|previous| &previous.token_type) == Some(&TokenType::LeftBrace);
+
+        self.out.push('\n');
+
+        // a single blank line is kept, but not right inside the braces of a block
+        if newlines > 1 && !after_open_brace && !closes_block {
+            self.out.push('\n');
+        }
+
+        self.line_indent = indent;
+        self.out.push_str(&INDENT.repeat(indent));
+    }
+}
+
+// comments in the whitespace between two tokens and the line breaks after the last one
+fn comments(gap: &str) -> (Vec<Comment<'_>>, usize) {
+    let mut comments = Vec::new();
+    let mut newlines = 0;
+    let mut rest = gap;
+
+    while let Some(c) = rest.chars().next() {
+        let length = if rest.starts_with("//") {
+            rest.find('\n').unwrap_or(rest.len())
+        } else if rest.starts_with("/*") {
+            block_comment_length(rest)
+        } else {
+            if c == '\n' {
+                newlines += 1;
+            }
+
+            rest = &rest[c.len_utf8()..];
+
+            continue;
+        };
+
+        comments.push(Comment {
+            newlines,
+            text: rest[..length].trim_end(),
+        });
+
+        newlines = 0;
+        rest = &rest[length..];
+    }
+
+    (comments, newlines)
+}
+
+// block comments nest, the scanner already made sure this one is closed
+fn block_comment_length(text: &str) -> usize {
+    let mut depth = 0;
+    let mut index = 0;
+
+    while index < text.len() {
+        if text[index..].starts_with("/*") {
+            depth += 1;
+            index += 2;
+        } else if text[index..].starts_with("*/") {
+            depth -= 1;
+            index += 2;
+
+            if depth == 0 {
+                return index;
+            }
+        } else {
+            index += text[index..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+
+    text.len()
+}
+
+// whether `-` or `!` after `previous` negates instead of subtracting
+fn ends_operand(previous: &Token) -> bool {
+    matches!(
+        previous.token_type,
+        TokenType::Identifier
+            | TokenType::String { .. }
+            | TokenType::Number { .. }
+            | TokenType::Integer { .. }
+            | TokenType::BigInteger { .. }
+            | TokenType::Decimal { .. }
+            | TokenType::True
+            | TokenType::False
+            | TokenType::Nil
+            | TokenType::This
+            | TokenType::Super
+            | TokenType::RightParen
+            | TokenType::RightBracket
+    )
+}
+
+// `after_unary` when `previous` is a `-` or `!` that negates what follows
+fn needs_space(previous: &Token, next: &Token, in_pattern: bool, after_unary: bool) -> bool {
+    use TokenType::*;
+
+    match (&previous.token_type, &next.token_type) {
+        (_, Semicolon)
+        | (_, Comma)
+        | (_, RightParen)
+        | (_, RightBracket)
+        | (_, Dot)
+        | (_, Colon) => false,
+        (LeftParen, _) | (LeftBracket, _) | (Dot, _) => false,
+        (LeftBrace, _) | (_, RightBrace) if in_pattern => false,
+        // calls
+        (_, LeftParen) | (_, LeftBracket) if ends_operand(previous) => false,
+        (Minus, _) | (Bang, _) => !after_unary,
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::format;
+
+    use crate::astprinter::AstPrinter;
+    use crate::diagnostic::Diagnostics;
+    use crate::parser::parse_source;
+    use crate::scanner::Scanner;
+
+    fn formatted(source: &str) -> String {
+        let mut diagnostics = Diagnostics::default();
+        let tokens = Scanner::new(source).scan_tokens(&mut diagnostics).clone();
+
+        format(source, &tokens)
+    }
+
+    #[test]
+    fn spacing() {
+        assert_eq!(
+            formatted("var  a=-1+ 2*(3-x) ;"),
+            "var a = -1 + 2 * (3 - x);\n"
+        );
+        assert_eq!(formatted("print !f( a,b )[0];"), "print !f(a, b)[0];\n");
+        assert_eq!(
+            formatted("var {x,y}=p; var [a , b]=l;"),
+            "var {x, y} = p;\nvar [a, b] = l;\n"
+        );
+        assert_eq!(
+            formatted("fun f(a:Int):Int{return a;}"),
+            "fun f(a: Int): Int {\n  return a;\n}\n"
+        );
+    }
+
+    const MESSY: &str = "// leading comment\n\
+        var a = 1;    // trailing\n\n\n\
+        fun f(x) {\n\
+        /// doc\n\
+        if (x > 1) { print x; }\n\
+        else {\n\
+        return -x; }\n\
+        for (var i = 0; i < 3; i = i + 1) print i;\n\
+        }\n\
+        var list = [\n\
+        1, /* one */\n\
+        2\n\
+        ];\n\
+        while (a) {}\n";
+
+    #[test]
+    fn shebang_is_kept() {
+        assert_eq!(
+            formatted("#!/usr/bin/env yaliir\n\n\nprint  1;"),
+            "#!/usr/bin/env yaliir\n\nprint 1;\n"
+        );
+    }
+
+    #[test]
+    fn layout() {
+        assert_eq!(
+            formatted(MESSY),
+            "// leading comment\n\
+             var a = 1; // trailing\n\
+             \n\
+             fun f(x) {\n\
+             \x20\x20/// doc\n\
+             \x20\x20if (x > 1) {\n\
+             \x20\x20\x20\x20print x;\n\
+             \x20\x20} else {\n\
+             \x20\x20\x20\x20return -x;\n\
+             \x20\x20}\n\
+             \x20\x20for (var i = 0; i < 3; i = i + 1) print i;\n\
+             }\n\
+             var list = [\n\
+             \x20\x201, /* one */\n\
+             \x20\x202\n\
+             ];\n\
+             while (a) {}\n"
+        );
+    }
+
+    #[test]
+    fn formatting_is_stable_and_keeps_meaning() {
+        let once = formatted(MESSY);
+
+        assert_eq!(formatted(&once), once);
+
+        let tree = |source: &str| {
+            let mut diagnostics = Diagnostics::default();
+            let statements = parse_source(source, &mut diagnostics);
+
+            assert!(!diagnostics.has_errors());
+
+            statements
+                .iter()
+                .map(|statement| AstPrinter.print_stmt(statement))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(tree(&once), tree(MESSY));
+    }
+}
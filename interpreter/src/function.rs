@@ -1,23 +1,28 @@
 use std::cell::RefCell;
+use std::env;
 use std::fmt;
+use std::fs;
 use std::rc::Rc;
 use std::time::SystemTime;
 
 use crate::callable::Callable;
 use crate::environment::{Environment, SharedEnv};
 use crate::error::LoxError;
-use crate::interpreter::Interpreter;
+use crate::interpreter::{DecimalContext, Interpreter};
 use crate::object::Object;
+use crate::scheduler::SharedChannel;
 use crate::statement::Stmt;
 use crate::token::Token;
 
+use rust_decimal::RoundingStrategy;
+
 #[derive(Clone)]
 pub enum Function {
     Native {
         arity: usize,
         function: fn(
             function: &Self,
-            interpreter: &Interpreter,
+            interpreter: &mut Interpreter,
             arguments: &[Object],
         ) -> Result<Object, LoxError>,
     },
@@ -26,6 +31,7 @@ pub enum Function {
         params: Vec<Token>,
         body: Vec<Stmt>,
         closure: SharedEnv,
+        doc: Option<String>,
     },
 }
 
@@ -50,18 +56,17 @@ impl Callable for Function {
                 closure,
                 ..
             } => {
-                let mut environment = Rc::new(RefCell::new(Environment::from(closure)));
+                let mut environment = Environment::from(closure);
 
                 for (param, argument) in params.iter().zip(arguments) {
-                    Rc::get_mut(&mut environment)
-                        .unwrap()
-                        .get_mut()
-                        .define(&param.lexeme, argument);
+                    environment.define(&param.lexeme, argument);
                 }
 
-                if let Err(err) = interpreter.execute_block(&body, &environment) {
+                let environment = Rc::new(RefCell::new(environment));
+
+                if let Err(err) = interpreter.execute_block(body, &environment) {
                     return match err {
-                        LoxError::Return(value) => Ok(value),
+                        LoxError::Return(value) => Ok(*value),
                         err => Err(err),
                     };
                 }
@@ -93,31 +98,337 @@ impl fmt::Debug for Function {
                 params,
                 body,
                 closure,
+                doc,
             } => f
                 .debug_struct("Function::User")
                 .field("name", name)
                 .field("params", params)
                 .field("body", body)
                 .field("closure", closure)
+                .field("doc", doc)
                 .finish(),
         }
     }
 }
 
 impl Function {
+    /// Parameters as written in the declaration, `_` for each one of a native function
+    pub fn param_list(&self) -> String {
+        match self {
+            Function::Native { arity, .. } => vec!["_"; *arity].join(", "),
+            Function::User { params, .. } => Function::param_names(params),
+        }
+    }
+
+    fn param_names(params: &[Token]) -> String {
+        params
+            .iter()
+            .map(|param| param.lexeme.clone())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
     // Native functions
     pub fn new_native_fn_clock() -> Self {
         Function::Native {
             arity: 0,
-            function: |_, _, _| {
+            function: |_, interpreter, _| {
+                if let Some(now) = interpreter.event_loop().virtual_time() {
+                    return Ok(Object::Number(now as f64 / 1000.0));
+                }
+
                 let start = SystemTime::now();
-                Ok(Object::Number(
-                    start
-                        .duration_since(SystemTime::UNIX_EPOCH)
-                        .expect("unable to get system time")
-                        .as_secs_f64(),
+                let since_epoch = start
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .map_err(|_| LoxError::Error("System time is before 1970.".to_owned()))?;
+
+                Ok(Object::Number(since_epoch.as_secs_f64()))
+            },
+        }
+    }
+
+    pub fn new_native_fn_help() -> Self {
+        Function::Native {
+            arity: 1,
+            function: |_, _, arguments| {
+                let text = match &arguments[0] {
+                    Object::Callable(Function::User {
+                        doc: Some(doc),
+                        name,
+                        params,
+                        ..
+                    }) => format!(
+                        "fun {}({})\n{}",
+                        name.lexeme,
+                        Function::param_names(params),
+                        doc
+                    ),
+                    value => format!("No documentation for {}.", value),
+                };
+
+                Ok(Object::String(text))
+            },
+        }
+    }
+
+    pub fn new_native_fn_set_decimal_rounding() -> Self {
+        Function::Native {
+            arity: 2,
+            function: |_, interpreter, arguments| {
+                let scale = match &arguments[0] {
+                    Object::Int(scale) if (0..=28).contains(scale) => *scale as u32,
+                    _ => {
+                        return Err(LoxError::Error(
+                            "Decimal scale must be an integer between 0 and 28.".to_owned(),
+                        ))
+                    }
+                };
+
+                let rounding = match &arguments[1] {
+                    Object::String(mode) => match &mode[..] {
+                        "half_even" => RoundingStrategy::MidpointNearestEven,
+                        "half_up" => RoundingStrategy::MidpointAwayFromZero,
+                        "half_down" => RoundingStrategy::MidpointTowardZero,
+                        "up" => RoundingStrategy::AwayFromZero,
+                        "down" => RoundingStrategy::ToZero,
+                        "ceiling" => RoundingStrategy::ToPositiveInfinity,
+                        "floor" => RoundingStrategy::ToNegativeInfinity,
+                        _ => {
+                            return Err(LoxError::Error(format!(
+                                "Unknown rounding mode '{}'.",
+                                mode
+                            )))
+                        }
+                    },
+                    _ => {
+                        return Err(LoxError::Error(
+                            "Rounding mode must be a string.".to_owned(),
+                        ))
+                    }
+                };
+
+                interpreter.set_decimal_context(DecimalContext { scale, rounding });
+
+                Ok(Object::Null)
+            },
+        }
+    }
+
+    pub fn new_native_fn_channel() -> Self {
+        Function::Native {
+            arity: 0,
+            function: |_, _, _| Ok(Object::Channel(SharedChannel::default())),
+        }
+    }
+
+    pub fn new_native_fn_send() -> Self {
+        Function::Native {
+            arity: 2,
+            function: |_, interpreter, arguments| match &arguments[0] {
+                Object::Channel(channel) => {
+                    interpreter.send(channel, arguments[1].clone());
+
+                    Ok(Object::Null)
+                }
+                _ => Err(LoxError::Error("Can only send to channels.".to_owned())),
+            },
+        }
+    }
+
+    pub fn new_native_fn_recv() -> Self {
+        Function::Native {
+            arity: 1,
+            function: |_, interpreter, arguments| match &arguments[0] {
+                Object::Channel(channel) => interpreter.receive(channel),
+                _ => Err(LoxError::Error(
+                    "Can only receive from channels.".to_owned(),
+                )),
+            },
+        }
+    }
+
+    pub fn new_native_fn_yield() -> Self {
+        Function::Native {
+            arity: 0,
+            function: |_, interpreter, _| {
+                interpreter.yield_now()?;
+
+                Ok(Object::Null)
+            },
+        }
+    }
+
+    pub fn new_native_fn_set_timeout() -> Self {
+        Function::Native {
+            arity: 2,
+            function: |_, interpreter, arguments| {
+                let (callback, delay) = timer_arguments(arguments)?;
+
+                Ok(Object::Int(
+                    interpreter.event_loop().set_timeout(callback, delay) as i64,
+                ))
+            },
+        }
+    }
+
+    pub fn new_native_fn_set_interval() -> Self {
+        Function::Native {
+            arity: 2,
+            function: |_, interpreter, arguments| {
+                let (callback, interval) = timer_arguments(arguments)?;
+
+                Ok(Object::Int(
+                    interpreter.event_loop().set_interval(callback, interval) as i64,
                 ))
             },
         }
     }
+
+    pub fn new_native_fn_clear_timer() -> Self {
+        Function::Native {
+            arity: 1,
+            function: |_, interpreter, arguments| match &arguments[0] {
+                Object::Int(id) if *id >= 0 => {
+                    interpreter.event_loop().clear_timer(*id as usize);
+
+                    Ok(Object::Null)
+                }
+                _ => Err(LoxError::Error("Timer id must be an integer.".to_owned())),
+            },
+        }
+    }
+
+    pub fn new_native_fn_sleep() -> Self {
+        Function::Native {
+            arity: 1,
+            function: |_, interpreter, arguments| {
+                let delay = milliseconds(&arguments[0])?;
+
+                Ok(Object::Future(interpreter.event_loop().sleep(delay)))
+            },
+        }
+    }
+
+    pub fn new_native_fn_read_file() -> Self {
+        Function::Native {
+            arity: 1,
+            function: |_, interpreter, arguments| match &arguments[0] {
+                Object::String(path) => {
+                    let path = path.clone();
+
+                    Ok(Object::Future(interpreter.event_loop().spawn_io(
+                        move || {
+                            fs::read_to_string(&path)
+                                .map_err(|err| format!("Could not read '{}': {}.", path, err))
+                        },
+                    )))
+                }
+                _ => Err(LoxError::Error("Path must be a string.".to_owned())),
+            },
+        }
+    }
+
+    pub fn new_native_fn_await() -> Self {
+        Function::Native {
+            arity: 1,
+            function: |_, interpreter, arguments| match &arguments[0] {
+                Object::Future(future) => interpreter.await_future(future),
+                // awaiting a plain value is allowed and returns it right away
+                value => Ok(value.clone()),
+            },
+        }
+    }
+
+    pub fn new_native_fn_env() -> Self {
+        Function::Native {
+            arity: 1,
+            function: |_, _, arguments| match &arguments[0] {
+                // unset and non Unicode variables are both nil
+                Object::String(name) => Ok(env::var(name).map_or(Object::Null, Object::String)),
+                _ => Err(LoxError::Error(
+                    "Environment variable name must be a string.".to_owned(),
+                )),
+            },
+        }
+    }
+
+    pub fn new_native_fn_set_env() -> Self {
+        Function::Native {
+            arity: 2,
+            function: |_, _, arguments| {
+                let name = match &arguments[0] {
+                    Object::String(name) if !name.is_empty() && !name.contains(['=', '\0']) => name,
+                    _ => {
+                        return Err(LoxError::Error(
+                            "Environment variable name must be a non-empty string without '=' \
+                             or NUL."
+                                .to_owned(),
+                        ))
+                    }
+                };
+
+                match &arguments[1] {
+                    Object::String(value) if !value.contains('\0') => env::set_var(name, value),
+                    Object::Null => env::remove_var(name),
+                    _ => {
+                        return Err(LoxError::Error(
+                            "Environment variable value must be a string without NUL or nil."
+                                .to_owned(),
+                        ))
+                    }
+                }
+
+                Ok(Object::Null)
+            },
+        }
+    }
+
+    pub fn new_native_fn_exit() -> Self {
+        Function::Native {
+            arity: 1,
+            function: |_, _, arguments| match &arguments[0] {
+                Object::Int(code) if (0..=255).contains(code) => Err(LoxError::Exit(*code as i32)),
+                _ => Err(LoxError::Error(
+                    "Exit code must be an integer between 0 and 255.".to_owned(),
+                )),
+            },
+        }
+    }
+
+    pub fn new_native_fn_stack_trace() -> Self {
+        Function::Native {
+            arity: 0,
+            function: |_, interpreter, _| {
+                let frames = interpreter
+                    .call_stack
+                    .iter()
+                    .rev()
+                    .map(|frame| Object::String(frame.to_string()))
+                    .collect::<Vec<_>>();
+
+                Ok(Object::List(Rc::new(frames)))
+            },
+        }
+    }
+}
+
+fn milliseconds(value: &Object) -> Result<u64, LoxError> {
+    match value {
+        Object::Int(ms) if *ms >= 0 => Ok(*ms as u64),
+        Object::Number(ms) if *ms >= 0.0 => Ok(*ms as u64),
+        _ => Err(LoxError::Error(
+            "Delay must be a non-negative number of milliseconds.".to_owned(),
+        )),
+    }
+}
+
+fn timer_arguments(arguments: &[Object]) -> Result<(Function, u64), LoxError> {
+    match &arguments[0] {
+        Object::Callable(callback) if callback.arity() == 0 => {
+            Ok((callback.clone(), milliseconds(&arguments[1])?))
+        }
+        _ => Err(LoxError::Error(
+            "Timer callback must be a function without parameters.".to_owned(),
+        )),
+    }
 }
@@ -1,19 +1,261 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell, RefMut};
+use std::cmp::Ordering;
 use std::convert::From;
+use std::fmt;
 use std::rc::Rc;
 
+use num_bigint::BigInt;
+use num_traits::{Signed, ToPrimitive, Zero};
+use rust_decimal::{Decimal, RoundingStrategy};
+
 use crate::callable::Callable;
+use crate::codes::{self, ErrorCode};
 use crate::environment::{Environment, SharedEnv};
 use crate::error::LoxError;
+use crate::event_loop::EventLoop;
 use crate::expression::{self, Expr};
 use crate::function::Function;
 use crate::object::Object;
-use crate::statement::{self, Stmt};
+use crate::scheduler::{Scheduler, TaskContext};
+use crate::span::Label;
+use crate::statement::{self, Pattern, Stmt};
 use crate::token::{Token, TokenType};
 
-pub struct Interpreter {
+// Applied to results of decimal multiplication and division, the operations that can need more
+// places than wanted
+#[derive(Clone, Copy, Debug)]
+pub struct DecimalContext {
+    pub scale: u32,
+    pub rounding: RoundingStrategy,
+}
+
+impl Default for DecimalContext {
+    fn default() -> Self {
+        Self {
+            scale: 20,
+            rounding: RoundingStrategy::MidpointNearestEven,
+        }
+    }
+}
+
+impl DecimalContext {
+    fn multiply(&self, left: Decimal, right: Decimal) -> Option<Decimal> {
+        let places = left.scale() + right.scale();
+
+        self.fit(
+            &(BigInt::from(left.mantissa()) * BigInt::from(right.mantissa())),
+            &BigInt::from(1),
+            i64::from(places),
+            places,
+        )
+    }
+
+    fn divide(&self, left: Decimal, right: Decimal) -> Option<Decimal> {
+        let exponent = i64::from(left.scale()) - i64::from(right.scale());
+
+        self.fit(
+            &BigInt::from(left.mantissa()),
+            &BigInt::from(right.mantissa()),
+            exponent,
+            exponent.max(0) as u32,
+        )
+    }
+
+    // `numerator / denominator / 10^exponent` with the fewest places from `preferred` on that hold
+    // it exactly. Anything else is rounded once from the exact value to `scale` places, or fewer if
+    // the digits do not fit into a decimal.
+    fn fit(
+        &self,
+        numerator: &BigInt,
+        denominator: &BigInt,
+        exponent: i64,
+        preferred: u32,
+    ) -> Option<Decimal> {
+        for places in preferred..=self.scale {
+            let (mantissa, exact) = self.scaled(numerator, denominator, exponent, places);
+
+            if exact {
+                if let Some(value) = decimal_with_places(&mantissa, places) {
+                    return Some(value);
+                }
+
+                break;
+            }
+        }
+
+        (0..=self.scale).rev().find_map(|places| {
+            let (mantissa, _) = self.scaled(numerator, denominator, exponent, places);
+
+            decimal_with_places(&mantissa, places)
+        })
+    }
+
+    // the value times 10^places rounded to an integer, and whether that was exact
+    fn scaled(
+        &self,
+        numerator: &BigInt,
+        denominator: &BigInt,
+        exponent: i64,
+        places: u32,
+    ) -> (BigInt, bool) {
+        let shift = i64::from(places) - exponent;
+        let ten = BigInt::from(10);
+
+        let (mut numerator, mut denominator) = if shift >= 0 {
+            (numerator * ten.pow(shift as u32), denominator.clone())
+        } else {
+            (
+                numerator.clone(),
+                denominator * ten.pow(shift.unsigned_abs() as u32),
+            )
+        };
+
+        if denominator.is_negative() {
+            numerator = -numerator;
+            denominator = -denominator;
+        }
+
+        // both truncate toward zero
+        let quotient = &numerator / &denominator;
+        let remainder = &numerator % &denominator;
+
+        if remainder.is_zero() {
+            return (quotient, true);
+        }
+
+        let negative = numerator.is_negative();
+        let twice_remainder: BigInt = remainder.abs() * 2;
+        let midpoint = twice_remainder.cmp(&denominator);
+
+        #[allow(deprecated)]
+        let away_from_zero = match self.rounding {
+            RoundingStrategy::MidpointNearestEven | RoundingStrategy::BankersRounding => {
+                midpoint == Ordering::Greater || (midpoint == Ordering::Equal && quotient.bit(0))
+            }
+            RoundingStrategy::MidpointAwayFromZero | RoundingStrategy::RoundHalfUp => {
+                midpoint != Ordering::Less
+            }
+            RoundingStrategy::MidpointTowardZero | RoundingStrategy::RoundHalfDown => {
+                midpoint == Ordering::Greater
+            }
+            RoundingStrategy::ToZero | RoundingStrategy::RoundDown => false,
+            RoundingStrategy::AwayFromZero | RoundingStrategy::RoundUp => true,
+            RoundingStrategy::ToNegativeInfinity => negative,
+            RoundingStrategy::ToPositiveInfinity => !negative,
+        };
+
+        let rounded = match (away_from_zero, negative) {
+            (false, _) => quotient,
+            (true, false) => quotient + 1,
+            (true, true) => quotient - 1,
+        };
+
+        (rounded, false)
+    }
+}
+
+fn decimal_with_places(mantissa: &BigInt, places: u32) -> Option<Decimal> {
+    Decimal::try_from_i128_with_scale(mantissa.to_i128()?, places).ok()
+}
+
+struct ArithmeticOps {
+    int: fn(i64, i64) -> Option<i64>,
+    big: fn(&BigInt, &BigInt) -> BigInt,
+    decimal: fn(&DecimalContext, Decimal, Decimal) -> Option<Decimal>,
+    float: fn(f64, f64) -> f64,
+}
+
+const ADD: ArithmeticOps = ArithmeticOps {
+    int: i64::checked_add,
+    big: |a, b| a + b,
+    decimal: |_, a, b| a.checked_add(b),
+    float: |a, b| a + b,
+};
+
+const SUBTRACT: ArithmeticOps = ArithmeticOps {
+    int: i64::checked_sub,
+    big: |a, b| a - b,
+    decimal: |_, a, b| a.checked_sub(b),
+    float: |a, b| a - b,
+};
+
+const MULTIPLY: ArithmeticOps = ArithmeticOps {
+    int: i64::checked_mul,
+    big: |a, b| a * b,
+    decimal: DecimalContext::multiply,
+    float: |a, b| a * b,
+};
+
+const DIVIDE: ArithmeticOps = ArithmeticOps {
+    int: i64::checked_div,
+    big: |a, b| a / b,
+    decimal: DecimalContext::divide,
+    float: |a, b| a / b,
+};
+
+// Debug builds use kilobytes of stack per level of the parsed tree, this fits the deepest tree the
+// parser lets through on top of `CALL_STACK_BUDGET`. The main script and every task get this much.
+pub const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+// deep enough for recursive code, shallow enough to fit `STACK_SIZE`
+const MAX_CALL_DEPTH: usize = 500;
+
+// Calls with big bodies can use up the stack before `MAX_CALL_DEPTH`, so calls also stop at this
+// much stack. The rest is for walking the deepest body the parser lets through.
+const CALL_STACK_BUDGET: usize = STACK_SIZE / 2;
+
+// roughly where the stack pointer is
+fn stack_address() -> usize {
+    let marker = 0u8;
+
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+// A running user function, kept to print tracebacks
+#[derive(Clone, Debug)]
+pub struct Frame {
+    pub function: String,
+    // None for tasks started by the event loop
+    pub call_site: Option<Token>,
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.call_site {
+            Some(token) => write!(
+                f,
+                "{}, called at {}:{}",
+                self.function, token.line, token.column
+            ),
+            None => write!(f, "{}, called by the event loop", self.function),
+        }
+    }
+}
+
+// What the main script and the tasks it spawned work on together. Every task runs on an
+// interpreter of its own, so this is only ever reached through shared references.
+pub(crate) struct Shared {
     globals: SharedEnv,
-    environment: SharedEnv,
+    pub(crate) scheduler: RefCell<Scheduler>,
+    pub(crate) event_loop: RefCell<EventLoop>,
+    decimal_context: Cell<DecimalContext>,
+    // statements and calls left before giving up, None runs forever. Keeps untrusted code like
+    // fuzzer input from hanging.
+    fuel: Cell<Option<u64>>,
+}
+
+pub struct Interpreter {
+    pub(crate) shared: Rc<Shared>,
+    pub(crate) environment: SharedEnv,
+    // outermost call first
+    pub(crate) call_stack: Vec<Frame>,
+    // call stack at the point the last error was raised
+    pub(crate) trace: Option<Vec<Frame>>,
+    // None for the main script
+    pub(crate) task: Option<TaskContext>,
+    // near the start of the stack this interpreter runs on, the thread or the task that made it
+    stack_base: usize,
+    pub asserts_enabled: bool,
 }
 
 impl Interpreter {
@@ -21,21 +263,153 @@ impl Interpreter {
         let mut globals = Environment::new();
 
         globals.define("clock", &Object::Callable(Function::new_native_fn_clock()));
+        globals.define("help", &Object::Callable(Function::new_native_fn_help()));
+        globals.define(
+            "channel",
+            &Object::Callable(Function::new_native_fn_channel()),
+        );
+        globals.define("send", &Object::Callable(Function::new_native_fn_send()));
+        globals.define("recv", &Object::Callable(Function::new_native_fn_recv()));
+        globals.define("yield", &Object::Callable(Function::new_native_fn_yield()));
+        globals.define(
+            "setTimeout",
+            &Object::Callable(Function::new_native_fn_set_timeout()),
+        );
+        globals.define(
+            "setInterval",
+            &Object::Callable(Function::new_native_fn_set_interval()),
+        );
+        globals.define(
+            "clearTimer",
+            &Object::Callable(Function::new_native_fn_clear_timer()),
+        );
+        globals.define("sleep", &Object::Callable(Function::new_native_fn_sleep()));
+        globals.define(
+            "readFile",
+            &Object::Callable(Function::new_native_fn_read_file()),
+        );
+        globals.define("await", &Object::Callable(Function::new_native_fn_await()));
+        globals.define("env", &Object::Callable(Function::new_native_fn_env()));
+        globals.define(
+            "setEnv",
+            &Object::Callable(Function::new_native_fn_set_env()),
+        );
+        globals.define("exit", &Object::Callable(Function::new_native_fn_exit()));
+        // filled in by whoever runs the script
+        globals.define("args", &Object::List(Rc::new(Vec::new())));
+        globals.define(
+            "stackTrace",
+            &Object::Callable(Function::new_native_fn_stack_trace()),
+        );
+        globals.define(
+            "setDecimalRounding",
+            &Object::Callable(Function::new_native_fn_set_decimal_rounding()),
+        );
+
+        let shared = Shared {
+            globals: SharedEnv::from(globals),
+            scheduler: RefCell::default(),
+            event_loop: RefCell::default(),
+            decimal_context: Cell::default(),
+            fuel: Cell::new(None),
+        };
 
-        let globals_shared = SharedEnv::from(globals);
+        Self::with_shared(Rc::new(shared), None, true)
+    }
 
+    // an interpreter starting out in the global scope of `shared`, also used for every task
+    pub(crate) fn with_shared(
+        shared: Rc<Shared>,
+        task: Option<TaskContext>,
+        asserts_enabled: bool,
+    ) -> Self {
         Self {
-            globals: Rc::clone(&globals_shared),
-            environment: globals_shared,
+            environment: Rc::clone(&shared.globals),
+            shared,
+            call_stack: Vec::new(),
+            trace: None,
+            task,
+            stack_base: stack_address(),
+            asserts_enabled,
         }
     }
 
+    pub(crate) fn scheduler(&self) -> RefMut<'_, Scheduler> {
+        self.shared.scheduler.borrow_mut()
+    }
+
+    pub(crate) fn event_loop(&self) -> RefMut<'_, EventLoop> {
+        self.shared.event_loop.borrow_mut()
+    }
+
+    pub fn set_decimal_context(&mut self, context: DecimalContext) {
+        self.shared.decimal_context.set(context);
+    }
+
+    // shared with every task the script spawns
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.shared.fuel.set(fuel);
+    }
+
+    // command line arguments after the script, the `args` list
+    pub fn set_args(&mut self, args: &[&str]) {
+        let args = args
+            .iter()
+            .map(|arg| Object::String(arg.to_string()))
+            .collect();
+
+        self.shared
+            .globals
+            .borrow_mut()
+            .define("args", &Object::List(Rc::new(args)));
+    }
+
+    // what the current scope can see, for inspecting a REPL session
+    pub fn bindings(&self) -> Vec<(String, Object)> {
+        self.environment.borrow().bindings()
+    }
+
+    // timers fire as soon as nothing else can run instead of waiting for real time to pass
+    pub fn use_virtual_clock(&mut self) {
+        self.event_loop().use_virtual_clock();
+    }
+
     pub fn interpret(&mut self, statements: &[Stmt]) -> Result<(), LoxError> {
-        for statement in statements {
-            self.execute(statement)?;
+        self.interpret_showing(statements, |_| {})
+    }
+
+    /// Like `interpret`, but passes the value of every top level expression statement other than
+    /// nil to `show`, which is how the REPL echoes `1 + 2;` as `=> 3`
+    pub fn interpret_showing(
+        &mut self,
+        statements: &[Stmt],
+        mut show: impl FnMut(&Object),
+    ) -> Result<(), LoxError> {
+        self.trace = None;
+
+        let result = statements
+            .iter()
+            .try_for_each(|statement| match statement {
+                Stmt::Expression { value } => {
+                    self.burn_fuel()?;
+
+                    let value = self.evaluate(value)?;
+
+                    if value != Object::Null {
+                        show(&value);
+                    }
+
+                    Ok(())
+                }
+                _ => self.execute(statement),
+            })
+            .and_then(|_| self.run_ready_tasks());
+
+        if result.is_err() {
+            self.reset_tasks();
         }
 
-        Ok(())
+        result
     }
 
     fn evaluate(&mut self, expr: &Expr) -> Result<Object, LoxError> {
@@ -43,9 +417,23 @@ impl Interpreter {
     }
 
     fn execute(&mut self, stmt: &Stmt) -> Result<(), LoxError> {
+        self.burn_fuel()?;
+
         stmt.accept(self)
     }
 
+    fn burn_fuel(&mut self) -> Result<(), LoxError> {
+        match self.shared.fuel.get() {
+            Some(0) => Err(LoxError::Error("Ran out of fuel.".to_owned())),
+            Some(fuel) => {
+                self.shared.fuel.set(Some(fuel - 1));
+
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
     pub(crate) fn execute_block(
         &mut self,
         statements: &[Stmt],
@@ -70,62 +458,432 @@ impl Interpreter {
         result
     }
 
+    pub(crate) fn call_function(
+        &mut self,
+        function: &Function,
+        call_site: Option<&Token>,
+        arguments: &[Object],
+    ) -> Result<Object, LoxError> {
+        // natives are run by timers without executing any statements
+        self.burn_fuel()?;
+
+        let name = match function {
+            Function::User { name, .. } => name,
+            Function::Native { .. } => return function.call(self, arguments),
+        };
+
+        let message = if self.call_stack.len() >= MAX_CALL_DEPTH {
+            Some(format!(
+                "Stack overflow, calls nested over {} deep.",
+                MAX_CALL_DEPTH
+            ))
+        } else if self.stack_base.abs_diff(stack_address()) > CALL_STACK_BUDGET {
+            Some(format!(
+                "Stack overflow, {} nested calls use up the stack.",
+                self.call_stack.len()
+            ))
+        } else {
+            None
+        };
+
+        if let Some(message) = message {
+            return Err(match call_site {
+                Some(paren) => LoxError::Runtime {
+                    token: Box::new(paren.clone()),
+                    code: codes::STACK_OVERFLOW,
+                    message,
+                    labels: Vec::new(),
+                    notes: Vec::new(),
+                },
+                None => LoxError::Error(message),
+            });
+        }
+
+        self.call_stack.push(Frame {
+            function: name.lexeme.clone(),
+            call_site: call_site.cloned(),
+        });
+
+        let result = function.call(self, arguments);
+
+        // the innermost call sees the error first
+        if result.is_err() && self.trace.is_none() {
+            self.trace = Some(self.call_stack.clone());
+        }
+
+        self.call_stack.pop();
+
+        result
+    }
+
+    // innermost call first, empty if the last error was not raised inside a function
+    pub fn take_trace(&mut self) -> Vec<Frame> {
+        let mut trace = self.trace.take().unwrap_or_default();
+
+        trace.reverse();
+
+        trace
+    }
+
+    // evaluates callee and arguments and checks arity, shared by calls and spawn
+    fn prepare_call(
+        &mut self,
+        callee: &Expr,
+        paren: &Token,
+        arguments: &[Expr],
+    ) -> Result<(Function, Vec<Object>), LoxError> {
+        let callee_expr = callee;
+        let callee = self.evaluate(callee)?;
+
+        let mut args = Vec::with_capacity(arguments.len());
+
+        for argument in arguments {
+            args.push(self.evaluate(argument)?);
+        }
+
+        let function = match callee {
+            Object::Callable(function) => function,
+            e => {
+                return Err(LoxError::Runtime {
+                    token: Box::new(paren.clone()),
+                    code: codes::NOT_CALLABLE,
+                    message: "Can only call functions and classes.".to_owned(),
+                    labels: vec![Label::new(callee_expr.span(), e.type_name())],
+                    notes: Vec::new(),
+                });
+            }
+        };
+
+        if args.len() != function.arity() as usize {
+            return Err(LoxError::Runtime {
+                token: Box::new(paren.clone()),
+                code: codes::ARITY_MISMATCH,
+                message: format!(
+                    "Expected {} arguments but got {}.",
+                    function.arity(),
+                    args.len()
+                ),
+                labels: match &function {
+                    Function::User { name, .. } => {
+                        vec![Label::new(name.span(), "function defined here")]
+                    }
+                    Function::Native { .. } => Vec::new(),
+                },
+                notes: Vec::new(),
+            });
+        }
+
+        Ok((function, args))
+    }
+
+    // native functions have no token of their own to report errors at, they use the call's
+    fn locate_native_error(err: LoxError, paren: &Token) -> LoxError {
+        let (code, message) = match err {
+            LoxError::Error(message) => (codes::NATIVE_ERROR, message),
+            LoxError::Deadlock(message) => (codes::DEADLOCK, message),
+            err => return err,
+        };
+
+        LoxError::Runtime {
+            token: Box::new(paren.clone()),
+            code,
+            message,
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    fn assertion_failure(
+        keyword: &Token,
+        source: &str,
+        operands: Option<(Object, Object)>,
+        message: Option<Object>,
+    ) -> LoxError {
+        // strings are quoted to tell them apart from other values
+        let repr = |value: &Object| match value {
+            Object::String(value) => format!("{:?}", value),
+            value => value.to_string(),
+        };
+
+        let mut text = format!("Assertion failed: {}", source);
+
+        if let Some((left, right)) = operands {
+            text.push_str(&format!(
+                " (left: {}, right: {})",
+                repr(&left),
+                repr(&right)
+            ));
+        }
+
+        if let Some(message) = message {
+            text.push_str(&format!(": {}", message));
+        }
+
+        LoxError::Runtime {
+            token: Box::new(keyword.clone()),
+            code: codes::ASSERTION_FAILED,
+            message: text,
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    fn bind_pattern(&mut self, pattern: &Pattern, value: &Object) -> Result<(), LoxError> {
+        match pattern {
+            Pattern::Name(name) => {
+                self.environment.borrow_mut().define(&name.lexeme, value);
+
+                Ok(())
+            }
+            Pattern::List { bracket, elements } => {
+                let values = Self::destructure_list(bracket, value, elements.len())?;
+
+                for (element, value) in elements.iter().zip(values.iter()) {
+                    self.bind_pattern(element, value)?;
+                }
+
+                Ok(())
+            }
+            // the parser does not produce these yet
+            Pattern::Fields { brace, .. } => Err(Self::classes_unsupported(brace)),
+        }
+    }
+
+    fn destructure_list(
+        token: &Token,
+        value: &Object,
+        expected: usize,
+    ) -> Result<Rc<Vec<Object>>, LoxError> {
+        match value {
+            Object::List(values) if values.len() == expected => Ok(Rc::clone(values)),
+            Object::List(values) => Err(LoxError::Runtime {
+                token: Box::new(token.clone()),
+                code: codes::DESTRUCTURING_MISMATCH,
+                message: format!(
+                    "Expected {} values to unpack but got {}.",
+                    expected,
+                    values.len()
+                ),
+                labels: Vec::new(),
+                notes: Vec::new(),
+            }),
+            _ => Err(LoxError::Runtime {
+                token: Box::new(token.clone()),
+                code: codes::DESTRUCTURING_MISMATCH,
+                message: "Can only destructure lists.".to_owned(),
+                labels: Vec::new(),
+                notes: Vec::new(),
+            }),
+        }
+    }
+
     fn operand_into_number(operator: &Token, operand: &Object) -> Result<f64, LoxError> {
         match operand {
             Object::Number(value) => Ok(*value),
-            _ => Err(LoxError::Runtime {
-                token: operator.clone(),
+            _ => operand.to_float().ok_or_else(|| LoxError::Runtime {
+                token: Box::new(operator.clone()),
+                code: codes::INVALID_OPERANDS,
                 message: "Operand must be a number.".to_owned(),
+                labels: Vec::new(),
+                notes: Vec::new(),
             }),
         }
     }
 
+    fn binary_operation(
+        &self,
+        left: &Object,
+        operator: &Token,
+        right: &Object,
+    ) -> Result<Object, LoxError> {
+        let context = self.shared.decimal_context.get();
+
+        Ok(match operator.token_type {
+            TokenType::Minus => Self::operands_subtract(left, right, operator, &context)?,
+            TokenType::Slash => Self::operands_divide(left, right, operator, &context)?,
+            TokenType::Star => Self::operands_multiply(left, right, operator, &context)?,
+            TokenType::Plus => Self::operands_add(left, right, operator, &context)?,
+            TokenType::Greater => Self::operands_cmp_gt(left, right, operator)?,
+            TokenType::GreaterEqual => Self::operands_cmp_ge(left, right, operator)?,
+            TokenType::Less => Self::operands_cmp_lt(left, right, operator)?,
+            TokenType::LessEqual => Self::operands_cmp_le(left, right, operator)?,
+            TokenType::BangEqual => Self::operands_cmp_ne(left, right, operator),
+            TokenType::EqualEqual => Self::operands_cmp_eq(left, right, operator),
+            _ => return Err(Self::unknown_operator(operator)),
+        })
+    }
+
+    // the parser only builds operator nodes for operators handled here
+    fn unknown_operator(operator: &Token) -> LoxError {
+        LoxError::Runtime {
+            token: Box::new(operator.clone()),
+            code: codes::INVALID_OPERANDS,
+            message: format!("Unknown operator '{}'.", operator.lexeme),
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    // the parser does not produce these nodes yet
+    fn classes_unsupported(token: &Token) -> LoxError {
+        LoxError::Runtime {
+            token: Box::new(token.clone()),
+            code: codes::UNSUPPORTED,
+            message: "Classes are not supported yet.".to_owned(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    fn numeric_error(operator: &Token, code: &'static ErrorCode, message: &str) -> LoxError {
+        LoxError::Runtime {
+            token: Box::new(operator.clone()),
+            code,
+            message: message.to_owned(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    // integers stay exact: i64 is promoted to a big integer on overflow, decimals never mix with floats
+    fn operands_arithmetic(
+        left: &Object,
+        right: &Object,
+        operator: &Token,
+        ops: &ArithmeticOps,
+        context: &DecimalContext,
+    ) -> Result<Object, LoxError> {
+        Self::operand_into_number(operator, left)?;
+        Self::operand_into_number(operator, right)?;
+
+        match (left, right) {
+            (Object::Int(int1), Object::Int(int2)) => Ok(match (ops.int)(*int1, *int2) {
+                Some(value) => Object::Int(value),
+                None => Object::from_big_int((ops.big)(&BigInt::from(*int1), &BigInt::from(*int2))),
+            }),
+            (Object::Decimal(_), Object::Number(_)) | (Object::Number(_), Object::Decimal(_)) => {
+                Err(Self::numeric_error(
+                    operator,
+                    codes::INVALID_OPERANDS,
+                    "Cannot mix decimal and float operands.",
+                ))
+            }
+            (Object::Number(_), _) | (_, Object::Number(_)) => Ok(Object::Number((ops.float)(
+                Self::operand_into_number(operator, left)?,
+                Self::operand_into_number(operator, right)?,
+            ))),
+            (Object::Decimal(_), _) | (_, Object::Decimal(_)) => {
+                match (left.to_decimal(), right.to_decimal()) {
+                    (Some(decimal1), Some(decimal2)) => (ops.decimal)(context, decimal1, decimal2)
+                        .map(Object::Decimal)
+                        .ok_or_else(|| {
+                            Self::numeric_error(
+                                operator,
+                                codes::NUMERIC_OVERFLOW,
+                                "Decimal overflow.",
+                            )
+                        }),
+                    _ => Err(Self::numeric_error(
+                        operator,
+                        codes::DECIMAL_CONVERSION,
+                        "Integer is too large to convert to decimal.",
+                    )),
+                }
+            }
+            _ => match (left.to_big_int(), right.to_big_int()) {
+                (Some(big1), Some(big2)) => Ok(Object::from_big_int((ops.big)(&big1, &big2))),
+                _ => Err(Self::numeric_error(
+                    operator,
+                    codes::INVALID_OPERANDS,
+                    "Operand must be a number.",
+                )),
+            },
+        }
+    }
+
     fn operands_subtract(
         left: &Object,
         right: &Object,
         operator: &Token,
+        context: &DecimalContext,
     ) -> Result<Object, LoxError> {
-        Ok(Object::Number(
-            Self::operand_into_number(operator, &left)?
-                - Self::operand_into_number(operator, &right)?,
-        ))
+        Self::operands_arithmetic(left, right, operator, &SUBTRACT, context)
     }
 
     fn operands_divide(
         left: &Object,
         right: &Object,
         operator: &Token,
+        context: &DecimalContext,
     ) -> Result<Object, LoxError> {
-        Ok(Object::Number(
-            Self::operand_into_number(operator, &left)?
-                / Self::operand_into_number(operator, &right)?,
-        ))
+        let is_zero = match right {
+            Object::Int(value) => *value == 0,
+            Object::Decimal(value) => value.is_zero(),
+            _ => false,
+        };
+
+        if is_zero && !matches!(left, Object::Number(_)) {
+            return Err(Self::numeric_error(
+                operator,
+                codes::DIVISION_BY_ZERO,
+                "Division by zero.",
+            ));
+        }
+
+        Self::operands_arithmetic(left, right, operator, &DIVIDE, context)
     }
 
     fn operands_multiply(
         left: &Object,
         right: &Object,
         operator: &Token,
+        context: &DecimalContext,
     ) -> Result<Object, LoxError> {
-        Ok(Object::Number(
-            Self::operand_into_number(operator, &left)?
-                * Self::operand_into_number(operator, &right)?,
-        ))
+        Self::operands_arithmetic(left, right, operator, &MULTIPLY, context)
     }
 
-    fn operands_add(left: &Object, right: &Object, operator: &Token) -> Result<Object, LoxError> {
-        Ok(match (&left, &right) {
-            (Object::Number(number1), Object::Number(number2)) => Object::Number(number1 + number2),
+    fn operands_add(
+        left: &Object,
+        right: &Object,
+        operator: &Token,
+        context: &DecimalContext,
+    ) -> Result<Object, LoxError> {
+        match (&left, &right) {
             (Object::String(string1), Object::String(string2)) => {
-                Object::String(string1.to_owned() + string2)
+                Ok(Object::String(string1.to_owned() + string2))
             }
-            _ => {
-                return Err(LoxError::Runtime {
-                    token: operator.clone(),
-                    message: "Operands must be two numbers or two strings.".to_owned(),
-                })
+            (left, right) if left.is_numeric() && right.is_numeric() => {
+                Self::operands_arithmetic(left, right, operator, &ADD, context)
             }
-        })
+            _ => Err(LoxError::Runtime {
+                token: Box::new(operator.clone()),
+                code: codes::INVALID_OPERANDS,
+                message: "Operands must be two numbers or two strings.".to_owned(),
+                labels: Vec::new(),
+                notes: Vec::new(),
+            }),
+        }
+    }
+
+    fn operands_compare(
+        left: &Object,
+        right: &Object,
+        operator: &Token,
+    ) -> Result<Option<Ordering>, LoxError> {
+        Self::operand_into_number(operator, left)?;
+        Self::operand_into_number(operator, right)?;
+
+        if let (Object::Decimal(_), Object::Number(_)) | (Object::Number(_), Object::Decimal(_)) =
+            (left, right)
+        {
+            return Err(Self::numeric_error(
+                operator,
+                codes::INVALID_OPERANDS,
+                "Cannot mix decimal and float operands.",
+            ));
+        }
+
+        Ok(left.numeric_cmp(right))
     }
 
     fn operands_cmp_gt(
@@ -133,10 +891,10 @@ impl Interpreter {
         right: &Object,
         operator: &Token,
     ) -> Result<Object, LoxError> {
-        Ok(Object::Boolean(
-            Self::operand_into_number(operator, &left)?
-                > Self::operand_into_number(operator, &right)?,
-        ))
+        Ok(Object::Boolean(matches!(
+            Self::operands_compare(left, right, operator)?,
+            Some(Ordering::Greater)
+        )))
     }
 
     fn operands_cmp_ge(
@@ -144,10 +902,10 @@ impl Interpreter {
         right: &Object,
         operator: &Token,
     ) -> Result<Object, LoxError> {
-        Ok(Object::Boolean(
-            Self::operand_into_number(operator, &left)?
-                >= Self::operand_into_number(operator, &right)?,
-        ))
+        Ok(Object::Boolean(matches!(
+            Self::operands_compare(left, right, operator)?,
+            Some(Ordering::Greater | Ordering::Equal)
+        )))
     }
 
     fn operands_cmp_lt(
@@ -155,10 +913,10 @@ impl Interpreter {
         right: &Object,
         operator: &Token,
     ) -> Result<Object, LoxError> {
-        Ok(Object::Boolean(
-            Self::operand_into_number(operator, &left)?
-                < Self::operand_into_number(operator, &right)?,
-        ))
+        Ok(Object::Boolean(matches!(
+            Self::operands_compare(left, right, operator)?,
+            Some(Ordering::Less)
+        )))
     }
 
     fn operands_cmp_le(
@@ -166,10 +924,10 @@ impl Interpreter {
         right: &Object,
         operator: &Token,
     ) -> Result<Object, LoxError> {
-        Ok(Object::Boolean(
-            Self::operand_into_number(operator, &left)?
-                <= Self::operand_into_number(operator, &right)?,
-        ))
+        Ok(Object::Boolean(matches!(
+            Self::operands_compare(left, right, operator)?,
+            Some(Ordering::Less | Ordering::Equal)
+        )))
     }
 
     fn operands_cmp_ne(left: &Object, right: &Object, _operator: &Token) -> Object {
@@ -185,7 +943,7 @@ impl expression::Visitor<Result<Object, LoxError>> for Interpreter {
     fn visit_assign(&mut self, name: &Token, value: &Expr) -> Result<Object, LoxError> {
         let value = self.evaluate(value)?;
 
-        self.environment.borrow_mut().assign(&name, &value)?;
+        self.environment.borrow_mut().assign(name, &value)?;
 
         Ok(value)
     }
@@ -196,22 +954,16 @@ impl expression::Visitor<Result<Object, LoxError>> for Interpreter {
         operator: &Token,
         right: &Expr,
     ) -> Result<Object, LoxError> {
-        let left = &self.evaluate(left)?;
-        let right = &self.evaluate(right)?;
-
-        Ok(match operator.token_type {
-            TokenType::Minus => Self::operands_subtract(left, right, operator)?,
-            TokenType::Slash => Self::operands_divide(left, right, operator)?,
-            TokenType::Star => Self::operands_multiply(&left, right, operator)?,
-            TokenType::Plus => Self::operands_add(left, right, operator)?,
-            TokenType::Greater => Self::operands_cmp_gt(left, right, operator)?,
-            TokenType::GreaterEqual => Self::operands_cmp_ge(left, right, operator)?,
-            TokenType::Less => Self::operands_cmp_lt(left, right, operator)?,
-            TokenType::LessEqual => Self::operands_cmp_le(left, right, operator)?,
-            TokenType::BangEqual => Self::operands_cmp_ne(left, right, operator),
-            TokenType::EqualEqual => Self::operands_cmp_eq(left, right, operator),
-            _ => unreachable!(),
-        })
+        let left_value = &self.evaluate(left)?;
+        let right_value = &self.evaluate(right)?;
+
+        self.binary_operation(left_value, operator, right_value)
+            .map_err(|err| {
+                err.with_labels([
+                    Label::new(left.span(), left_value.type_name()),
+                    Label::new(right.span(), right_value.type_name()),
+                ])
+            })
     }
 
     fn visit_call(
@@ -220,47 +972,30 @@ impl expression::Visitor<Result<Object, LoxError>> for Interpreter {
         paren: &Token,
         arguments: &[Expr],
     ) -> Result<Object, LoxError> {
-        let callee = self.evaluate(callee)?;
-
-        let mut args = Vec::with_capacity(arguments.len());
-
-        for argument in arguments {
-            args.push(self.evaluate(argument)?);
-        }
+        let (function, args) = self.prepare_call(callee, paren, arguments)?;
 
-        let function = match callee {
-            Object::Callable(function) => function,
-            e => {
-                println!("{:?}", e);
-                return Err(LoxError::Runtime {
-                    token: paren.clone(),
-                    message: "Can only call functions and classes.".to_owned(),
-                });
-            }
-        };
-
-        if args.len() != function.arity() as usize {
-            return Err(LoxError::Runtime {
-                token: paren.clone(),
-                message: format!(
-                    "Expected {} arguments but got {}.",
-                    function.arity(),
-                    args.len()
-                ),
-            });
-        }
-
-        function.call(self, &args)
+        self.call_function(&function, Some(paren), &args)
+            .map_err(|err| Self::locate_native_error(err, paren))
     }
 
-    fn visit_get(&mut self, object: &Expr, name: &Token) -> Result<Object, LoxError> {
-        todo!();
+    fn visit_get(&mut self, _object: &Expr, name: &Token) -> Result<Object, LoxError> {
+        Err(Self::classes_unsupported(name))
     }
 
     fn visit_grouping(&mut self, expression: &Expr) -> Result<Object, LoxError> {
         self.evaluate(expression)
     }
 
+    fn visit_list(&mut self, _bracket: &Token, elements: &[Expr]) -> Result<Object, LoxError> {
+        let mut values = Vec::with_capacity(elements.len());
+
+        for element in elements {
+            values.push(self.evaluate(element)?);
+        }
+
+        Ok(Object::List(Rc::new(values)))
+    }
+
     fn visit_literal(&mut self, object: &Object) -> Result<Object, LoxError> {
         Ok(object.clone())
     }
@@ -286,28 +1021,41 @@ impl expression::Visitor<Result<Object, LoxError>> for Interpreter {
 
     fn visit_set(
         &mut self,
-        object: &Expr,
+        _object: &Expr,
         token: &Token,
-        value: &Expr,
+        _value: &Expr,
     ) -> Result<Object, LoxError> {
-        todo!();
+        Err(Self::classes_unsupported(token))
     }
 
-    fn visit_super(&mut self, keyword: &Token, method: &Token) -> Result<Object, LoxError> {
-        todo!();
+    fn visit_super(&mut self, keyword: &Token, _method: &Token) -> Result<Object, LoxError> {
+        Err(Self::classes_unsupported(keyword))
     }
 
     fn visit_this(&mut self, keyword: &Token) -> Result<Object, LoxError> {
-        todo!();
+        Err(Self::classes_unsupported(keyword))
     }
 
     fn visit_unary(&mut self, operator: &Token, right: &Expr) -> Result<Object, LoxError> {
+        let operand = right;
         let right = self.evaluate(right)?;
 
         Ok(match operator.token_type {
             TokenType::Bang => Object::Boolean(!right.is_truthy()),
-            TokenType::Minus => Object::Number(-Self::operand_into_number(operator, &right)?),
-            _ => unreachable!(),
+            TokenType::Minus => {
+                match right {
+                    Object::Int(value) => match value.checked_neg() {
+                        Some(value) => Object::Int(value),
+                        None => Object::from_big_int(-BigInt::from(value)),
+                    },
+                    Object::BigInt(value) => Object::from_big_int(-value),
+                    Object::Decimal(value) => Object::Decimal(-value),
+                    _ => Object::Number(-Self::operand_into_number(operator, &right).map_err(
+                        |err| err.with_labels([Label::new(operand.span(), right.type_name())]),
+                    )?),
+                }
+            }
+            _ => return Err(Self::unknown_operator(operator)),
         })
     }
 
@@ -317,6 +1065,54 @@ impl expression::Visitor<Result<Object, LoxError>> for Interpreter {
 }
 
 impl statement::Visitor<Result<(), LoxError>> for Interpreter {
+    fn visit_assert(
+        &mut self,
+        keyword: &Token,
+        condition: &Expr,
+        message: &Option<Expr>,
+        source: &str,
+    ) -> Result<(), LoxError> {
+        if !self.asserts_enabled {
+            return Ok(());
+        }
+
+        // comparisons are evaluated by hand to be able to show both sides
+        let (passed, operands) = match condition {
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } if matches!(
+                operator.token_type,
+                TokenType::EqualEqual
+                    | TokenType::BangEqual
+                    | TokenType::Greater
+                    | TokenType::GreaterEqual
+                    | TokenType::Less
+                    | TokenType::LessEqual
+            ) =>
+            {
+                let left = self.evaluate(left)?;
+                let right = self.evaluate(right)?;
+                let result = self.binary_operation(&left, operator, &right)?;
+
+                (result.is_truthy(), Some((left, right)))
+            }
+            condition => (self.evaluate(condition)?.is_truthy(), None),
+        };
+
+        if passed {
+            return Ok(());
+        }
+
+        let message = match message {
+            Some(message) => Some(self.evaluate(message)?),
+            None => None,
+        };
+
+        Err(Self::assertion_failure(keyword, source, operands, message))
+    }
+
     fn visit_block(&mut self, statements: &[Stmt]) -> Result<(), LoxError> {
         self.execute_block(
             statements,
@@ -324,6 +1120,12 @@ impl statement::Visitor<Result<(), LoxError>> for Interpreter {
         )
     }
 
+    fn visit_destructure(&mut self, pattern: &Pattern, initializer: &Expr) -> Result<(), LoxError> {
+        let value = self.evaluate(initializer)?;
+
+        self.bind_pattern(pattern, &value)
+    }
+
     fn visit_expression(&mut self, value: &Expr) -> Result<(), LoxError> {
         self.evaluate(value).map(|_| {})
     }
@@ -332,13 +1134,17 @@ impl statement::Visitor<Result<(), LoxError>> for Interpreter {
         &mut self,
         name: &Token,
         params: &[Token],
+        _param_types: &[Option<Token>],
+        _return_type: &Option<Token>,
         body: &[Stmt],
+        doc: &Option<String>,
     ) -> Result<(), LoxError> {
         let function = Function::User {
             name: name.clone(),
             params: params.to_vec(),
             body: body.to_vec(),
             closure: Rc::clone(&self.environment),
+            doc: doc.clone(),
         };
 
         self.environment
@@ -363,6 +1169,36 @@ impl statement::Visitor<Result<(), LoxError>> for Interpreter {
         Ok(())
     }
 
+    fn visit_multi_assign(&mut self, targets: &[Token], values: &[Expr]) -> Result<(), LoxError> {
+        let mut objects = Vec::with_capacity(values.len());
+
+        for value in values {
+            objects.push(self.evaluate(value)?);
+        }
+
+        if objects.len() == 1 && targets.len() > 1 {
+            objects = Self::destructure_list(&targets[0], &objects[0], targets.len())?.to_vec();
+        } else if objects.len() != targets.len() {
+            return Err(LoxError::Runtime {
+                token: Box::new(targets[0].clone()),
+                code: codes::DESTRUCTURING_MISMATCH,
+                message: format!(
+                    "Expected {} values to assign but got {}.",
+                    targets.len(),
+                    objects.len()
+                ),
+                labels: Vec::new(),
+                notes: Vec::new(),
+            });
+        }
+
+        for (target, value) in targets.iter().zip(objects.iter()) {
+            self.environment.borrow_mut().assign(target, value)?;
+        }
+
+        Ok(())
+    }
+
     fn visit_print(&mut self, value: &Expr) -> Result<(), LoxError> {
         let value = self.evaluate(value)?;
 
@@ -371,6 +1207,19 @@ impl statement::Visitor<Result<(), LoxError>> for Interpreter {
         Ok(())
     }
 
+    fn visit_spawn(
+        &mut self,
+        _keyword: &Token,
+        callee: &Expr,
+        paren: &Token,
+        arguments: &[Expr],
+    ) -> Result<(), LoxError> {
+        let (function, args) = self.prepare_call(callee, paren, arguments)?;
+
+        self.spawn(function, args, Some(paren.clone()))
+            .map_err(|err| Self::locate_native_error(err, paren))
+    }
+
     fn visit_return(&mut self, _keyword: &Token, value: &Option<Expr>) -> Result<(), LoxError> {
         let value = if let Some(v) = value {
             self.evaluate(v)?
@@ -378,10 +1227,15 @@ impl statement::Visitor<Result<(), LoxError>> for Interpreter {
             Object::Null
         };
 
-        Err(LoxError::Return(value))
+        Err(LoxError::Return(Box::new(value)))
     }
 
-    fn visit_var(&mut self, name: &Token, initializer: &Option<Expr>) -> Result<(), LoxError> {
+    fn visit_var(
+        &mut self,
+        name: &Token,
+        _type_annotation: &Option<Token>,
+        initializer: &Option<Expr>,
+    ) -> Result<(), LoxError> {
         let mut value = Object::Null;
 
         if let Some(initializer) = initializer {
@@ -407,3 +1261,627 @@ impl Default for Interpreter {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+    use std::thread;
+
+    use super::{Interpreter, STACK_SIZE};
+
+    use rust_decimal::Decimal;
+
+    use crate::diagnostic::Diagnostics;
+    use crate::error::LoxError;
+    use crate::object::Object;
+    use crate::parser::parse_source;
+    use crate::token::{Token, TokenType};
+
+    fn run(source: &str) -> (Interpreter, Result<(), LoxError>) {
+        run_with(source, |_| {}, |_| {})
+    }
+
+    // `setup` gets the interpreter before anything runs, `show` every top level value
+    fn run_with(
+        source: &str,
+        setup: impl FnOnce(&mut Interpreter),
+        show: impl FnMut(&Object),
+    ) -> (Interpreter, Result<(), LoxError>) {
+        let statements = parse_source(source, &mut Diagnostics::default());
+
+        let mut interpreter = Interpreter::new();
+        interpreter.use_virtual_clock();
+
+        setup(&mut interpreter);
+
+        let result = interpreter
+            .interpret_showing(&statements, show)
+            .and_then(|_| interpreter.run_event_loop());
+
+        (interpreter, result)
+    }
+
+    fn global(interpreter: &Interpreter, name: &str) -> Object {
+        interpreter
+            .shared
+            .globals
+            .borrow()
+            .get(&Token {
+                token_type: TokenType::Identifier,
+                lexeme: name.to_owned(),
+                line: 0,
+                column: 0,
+                offset: 0,
+                length: 0,
+                doc: None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn top_level_expression_values_are_shown() {
+        let mut shown = Vec::new();
+        let (_, result) = run_with(
+            "1 + 2; var a = 4; a; print 5; nil; fun f() {} f(); [a];",
+            |_| {},
+            |value| shown.push(value.to_string()),
+        );
+
+        assert!(result.is_ok());
+        assert_eq!(shown, vec!["3", "4", "[4]"]);
+    }
+
+    #[test]
+    fn help_returns_documentation() {
+        let (interpreter, result) = run(
+            "/// Adds.\nfun add(a, b) { return a + b; }\nvar doc = help(add);\nvar none = help(1);",
+        );
+
+        assert!(result.is_ok());
+        assert_eq!(
+            global(&interpreter, "doc"),
+            Object::String("fun add(a, b)\nAdds.".to_owned())
+        );
+        assert_eq!(
+            global(&interpreter, "none"),
+            Object::String("No documentation for 1.".to_owned())
+        );
+    }
+
+    #[test]
+    fn destructure_list() {
+        let (interpreter, result) = run("var [a, [b, c]] = [1, [2, 3]];");
+
+        assert!(result.is_ok());
+        assert_eq!(global(&interpreter, "a"), Object::Int(1));
+        assert_eq!(global(&interpreter, "b"), Object::Int(2));
+        assert_eq!(global(&interpreter, "c"), Object::Int(3));
+    }
+
+    #[test]
+    fn destructure_arity_mismatch() {
+        let (_, result) = run("var [a, b] = [1, 2, 3];");
+
+        match result {
+            Err(LoxError::Runtime { token, message, .. }) => {
+                assert_eq!(token.token_type, TokenType::LeftBracket);
+                assert_eq!(message, "Expected 2 values to unpack but got 3.");
+            }
+            _ => panic!("expected runtime error"),
+        }
+    }
+
+    #[test]
+    fn multi_assign_swap() {
+        let (interpreter, result) = run("var a = 1; var b = 2; a, b = b, a;");
+
+        assert!(result.is_ok());
+        assert_eq!(global(&interpreter, "a"), Object::Int(2));
+        assert_eq!(global(&interpreter, "b"), Object::Int(1));
+    }
+
+    #[test]
+    fn multi_assign_from_list() {
+        let (interpreter, result) = run("var a; var b; a, b = [3, 4];");
+
+        assert!(result.is_ok());
+        assert_eq!(global(&interpreter, "a"), Object::Int(3));
+        assert_eq!(global(&interpreter, "b"), Object::Int(4));
+    }
+
+    #[test]
+    fn integer_arithmetic() {
+        let (interpreter, result) =
+            run("var a = 7 / 2; var b = 7.0 / 2; var c = 9007199254740993 + 1;");
+
+        assert!(result.is_ok());
+        assert_eq!(global(&interpreter, "a"), Object::Int(3));
+        assert_eq!(global(&interpreter, "b"), Object::Number(3.5));
+        assert_eq!(global(&interpreter, "c"), Object::Int(9007199254740994));
+        assert_eq!(global(&interpreter, "a").to_string(), "3");
+        assert_eq!(global(&interpreter, "b").to_string(), "3.5");
+        assert_eq!(Object::Number(1.0).to_string(), "1.0");
+    }
+
+    #[test]
+    fn integer_overflow_promotes() {
+        let (interpreter, result) = run(
+            "var a = 9223372036854775807 + 1; var b = a - 1; var c = 99999999999999999999 == 99999999999999999999;",
+        );
+
+        assert!(result.is_ok());
+        assert_eq!(global(&interpreter, "a").to_string(), "9223372036854775808");
+        assert_eq!(global(&interpreter, "b"), Object::Int(i64::MAX));
+        assert_eq!(global(&interpreter, "c"), Object::Boolean(true));
+    }
+
+    #[test]
+    fn integers_compare_exactly_with_floats() {
+        // 2^53 + 1 has no float of its own, rounding it to one made it equal to 2^53
+        let (interpreter, result) = run("
+            var a = 9007199254740993 == 9007199254740992.0;
+            var b = 9007199254740993 > 9007199254740992.0;
+            var c = 9007199254740992 == 9007199254740992.0;
+            var d = 9007199254740992.0 < 9007199254740993;
+            var e = 99999999999999999999 < 100000000000000000000.0;
+            var f = -3 < -2.5;
+            var g = 100000000000000000000000000000000 > 79228162514264337593543950335d;
+        ");
+
+        assert!(result.is_ok());
+
+        for (name, value) in [
+            ("a", false),
+            ("b", true),
+            ("c", true),
+            ("d", true),
+            ("e", true),
+            ("f", true),
+            ("g", true),
+        ] {
+            assert_eq!(
+                global(&interpreter, name),
+                Object::Boolean(value),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn decimal_arithmetic() {
+        let (interpreter, result) = run(
+            "var a = 1.10d + 2.20d; var b = 1.00d / 3; setDecimalRounding(2, \"half_up\"); var c = 2d / 3;",
+        );
+
+        assert!(result.is_ok());
+        assert_eq!(global(&interpreter, "a").to_string(), "3.30");
+        assert_eq!(
+            global(&interpreter, "b").to_string(),
+            "0.33333333333333333333"
+        );
+        assert_eq!(global(&interpreter, "c").to_string(), "0.67");
+        assert_eq!(
+            global(&interpreter, "a"),
+            Object::Decimal(Decimal::new(33, 1))
+        );
+    }
+
+    #[test]
+    fn decimal_rounding_happens_once() {
+        let (interpreter, result) = run("
+            var exact = 6.00d / 3;
+            setDecimalRounding(0, \"half_up\");
+            var below_half = 9999999999999999999999999999d / 19999999999999999999999999999d;
+            setDecimalRounding(2, \"half_up\");
+            var product = 1.25d * 1.5d;
+            var short = 1.5d * 1.5d;
+            setDecimalRounding(1, \"floor\");
+            var floor = -1d / 3;
+        ");
+
+        assert!(result.is_ok());
+
+        // rounding to 28 digits first made this exactly one half, which rounded up to 1
+        for (name, value) in [
+            ("exact", "2.00"),
+            ("below_half", "0"),
+            ("product", "1.88"),
+            ("short", "2.25"),
+            ("floor", "-0.4"),
+        ] {
+            assert_eq!(global(&interpreter, name).to_string(), value, "{}", name);
+        }
+
+        match run("print 100000000000000000000000000000000 * 1.5d;").1 {
+            Err(LoxError::Runtime { code, .. }) => {
+                assert_eq!(code.code, crate::codes::DECIMAL_CONVERSION.code)
+            }
+            _ => panic!("expected a conversion error"),
+        }
+    }
+
+    #[test]
+    fn decimal_float_mix() {
+        for source in ["1.5d + 1.5;", "1.5d < 2.0;", "1.5 >= 1.5d;"] {
+            let (_, result) = run(source);
+
+            assert!(
+                matches!(result, Err(LoxError::Runtime { message, .. }) if message == "Cannot mix decimal and float operands."),
+                "{}",
+                source
+            );
+        }
+
+        // like values of different types they are never equal
+        let (interpreter, result) = run(
+            "var a = 1.5d == 1.5; var b = 1.5d != 1.5; var c = [1.5d] == [1.5]; var d = 1.5d > 1;",
+        );
+
+        assert!(result.is_ok());
+        assert_eq!(global(&interpreter, "a"), Object::Boolean(false));
+        assert_eq!(global(&interpreter, "b"), Object::Boolean(true));
+        assert_eq!(global(&interpreter, "c"), Object::Boolean(false));
+        assert_eq!(global(&interpreter, "d"), Object::Boolean(true));
+    }
+
+    #[test]
+    fn assert_reports_source_and_operands() {
+        let (_, result) = run("var a = 1;\nassert a  +  1 == 3, \"math\";");
+
+        match result {
+            Err(LoxError::Runtime { token, message, .. }) => {
+                assert_eq!(token.line, 2);
+                assert_eq!(
+                    message,
+                    "Assertion failed: a + 1 == 3 (left: 2, right: 3): math"
+                );
+            }
+            _ => panic!("expected assertion failure"),
+        }
+    }
+
+    #[test]
+    fn assert_disabled() {
+        let (_, result) = run_with(
+            "assert false;",
+            |interpreter| interpreter.asserts_enabled = false,
+            |_| {},
+        );
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn tasks_communicate_over_channels() {
+        let (interpreter, result) = run("
+            fun worker(input, output) {
+                var value = recv(input);
+                while (value != nil) {
+                    send(output, value * 2);
+                    value = recv(input);
+                }
+            }
+            var input = channel();
+            var output = channel();
+            spawn worker(input, output);
+            var results = 0;
+            for (var i = 1; i <= 3; i = i + 1) {
+                send(input, i);
+                results = results * 100 + recv(output);
+            }
+            send(input, nil);
+        ");
+
+        assert!(result.is_ok());
+        assert_eq!(global(&interpreter, "results"), Object::Int(20406));
+    }
+
+    #[test]
+    fn tasks_interleave_on_yield() {
+        let (interpreter, result) = run("
+            var order = \"\";
+            fun task(name) {
+                for (var i = 0; i < 2; i = i + 1) {
+                    order = order + name;
+                    yield();
+                }
+            }
+            spawn task(\"a\");
+            spawn task(\"b\");
+        ");
+
+        assert!(result.is_ok());
+        assert_eq!(
+            global(&interpreter, "order"),
+            Object::String("abab".to_owned())
+        );
+    }
+
+    #[test]
+    fn deadlock_is_reported() {
+        let (_, result) = run("fun stuck(ch) { recv(ch); } spawn stuck(channel());");
+
+        assert!(
+            matches!(result, Err(LoxError::Deadlock(message)) if message.starts_with("Deadlock"))
+        );
+
+        let (_, result) = run("recv(channel());");
+
+        assert!(
+            matches!(result, Err(LoxError::Runtime { message, .. }) if message.starts_with("Deadlock"))
+        );
+    }
+
+    #[test]
+    fn timers_fire_in_virtual_time() {
+        let (interpreter, result) = run("
+            var ticks = 0;
+            var ticks_at_timeout;
+            var last_tick;
+            var id;
+            fun tick() {
+                ticks = ticks + 1;
+                last_tick = clock();
+                if (ticks == 3) clearTimer(id);
+            }
+            fun once() { ticks_at_timeout = ticks; }
+            id = setInterval(tick, 100);
+            setTimeout(once, 250);
+        ");
+
+        assert!(result.is_ok());
+        assert_eq!(global(&interpreter, "ticks"), Object::Int(3));
+        assert_eq!(global(&interpreter, "ticks_at_timeout"), Object::Int(2));
+        assert_eq!(global(&interpreter, "last_tick"), Object::Number(0.3));
+    }
+
+    #[test]
+    fn huge_delays_never_fire() {
+        let (interpreter, result) = run("
+            var ticks = 0;
+            var id;
+            fun tick() {
+                ticks = ticks + 1;
+                if (ticks == 2) clearTimer(id);
+            }
+            id = setInterval(tick, 100000000000000000000000.0);
+        ");
+
+        // the virtual clock jumps to the end of time once, there is no time for a second tick
+        assert!(result.is_ok());
+        assert_eq!(global(&interpreter, "ticks"), Object::Int(1));
+
+        // the real clock is past zero, so the delay does not fit in what is left
+        let statements = parse_source(
+            "fun f() {} var id = setTimeout(f, 100000000000000000000000.0); clearTimer(id);",
+            &mut Diagnostics::default(),
+        );
+        let mut interpreter = Interpreter::new();
+        thread::sleep(std::time::Duration::from_millis(2));
+
+        assert!(interpreter.interpret(&statements).is_ok());
+    }
+
+    #[test]
+    fn await_drives_event_loop() {
+        let (interpreter, result) = run("
+            var order = \"\";
+            fun task() {
+                await(sleep(20));
+                order = order + \"task\";
+            }
+            spawn task();
+            await(sleep(10));
+            order = order + \"main\";
+            var failed = readFile(\"/nonexistent/file\");
+        ");
+
+        assert!(result.is_ok());
+        assert_eq!(
+            global(&interpreter, "order"),
+            Object::String("maintask".to_owned())
+        );
+
+        let (_, result) = run("await(readFile(\"/nonexistent/file\"));");
+
+        assert!(
+            matches!(result, Err(LoxError::Runtime { message, .. }) if message.starts_with("Could not read"))
+        );
+    }
+
+    #[test]
+    fn runtime_errors_carry_labels() {
+        let (_, result) = run("fun add(a, b) { return a + b; }\nvar f = add;\nf(1);");
+
+        match result {
+            Err(LoxError::Runtime { token, labels, .. }) => {
+                assert_eq!((token.line, token.column), (3, 4));
+                assert_eq!(labels.len(), 1);
+                assert_eq!(labels[0].message, "function defined here");
+                assert_eq!((labels[0].span.line, labels[0].span.column), (1, 5));
+            }
+            _ => panic!("expected arity error"),
+        }
+
+        let (_, result) = run("var x = \"a\";\nprint 1 + x;");
+
+        match result {
+            Err(LoxError::Runtime { labels, .. }) => {
+                let labels = labels
+                    .iter()
+                    .map(|label| (label.span.column, label.message.as_str()))
+                    .collect::<Vec<_>>();
+
+                assert_eq!(labels, [(7, "integer"), (11, "string")]);
+            }
+            _ => panic!("expected operand error"),
+        }
+    }
+
+    #[test]
+    fn errors_keep_call_stack() {
+        let (mut interpreter, result) = run("
+            fun inner() { trace = stackTrace(); return nil + 1; }
+            fun outer() { inner(); }
+            var trace;
+            outer();
+        ");
+
+        assert!(result.is_err());
+        assert_eq!(
+            global(&interpreter, "trace"),
+            Object::List(
+                vec![
+                    Object::String("inner, called at 3:33".to_owned()),
+                    Object::String("outer, called at 5:19".to_owned()),
+                ]
+                .into()
+            )
+        );
+
+        let trace = interpreter
+            .take_trace()
+            .into_iter()
+            .map(|frame| frame.function)
+            .collect::<Vec<_>>();
+
+        assert_eq!(trace, ["inner", "outer"]);
+    }
+
+    #[test]
+    fn undefined_variable_suggests_similar_name() {
+        let notes = |source| match run(source).1 {
+            Err(LoxError::Runtime { notes, .. }) => notes,
+            _ => panic!("expected runtime error"),
+        };
+
+        assert_eq!(
+            notes("var length = 1;\nfun f(count) { return lenght + count; }\nf(1);"),
+            ["did you mean 'length'?"]
+        );
+        assert_eq!(
+            notes("fun f(count) { return cuont; }\nf(1);"),
+            ["did you mean 'count'?"]
+        );
+        assert_eq!(notes("pritn(1);"), ["did you mean 'print'?"]);
+        assert!(notes("unrelated = 1;").is_empty());
+    }
+
+    #[test]
+    fn runaway_recursion_is_an_error() {
+        // test threads get 2 MiB of stack, less than the main thread and tasks the limit is for
+        let error = thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(|| match run("fun f(n) { return f(n + 1); }\nf(0);").1 {
+                Err(LoxError::Runtime { code, token, .. }) => Some((code.code, token.line)),
+                _ => None,
+            })
+            .expect("could not spawn test thread")
+            .join()
+            .expect("test thread panicked");
+
+        assert_eq!(error, Some((crate::codes::STACK_OVERFLOW.code, 1)));
+    }
+
+    #[test]
+    fn long_chains_run_and_stop_runaway_recursion() {
+        let chain = " + 1".repeat(500);
+        let sum = format!("var sum = 1{};", chain);
+        // every call keeps a 500 link chain on the stack, far before the call depth limit
+        let recursion = format!("fun f(n) {{ return f(n + 1){}; }}\nf(0);", chain);
+
+        let (sum, error) = thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(move || {
+                let (interpreter, result) = run(&sum);
+                assert!(result.is_ok());
+
+                let error = match run(&recursion).1 {
+                    Err(LoxError::Runtime { code, token, .. }) => Some((code.code, token.line)),
+                    _ => None,
+                };
+
+                (global(&interpreter, "sum").to_string(), error)
+            })
+            .expect("could not spawn test thread")
+            .join()
+            .expect("test thread panicked");
+
+        assert_eq!(sum, "501");
+        assert_eq!(error, Some((crate::codes::STACK_OVERFLOW.code, 1)));
+    }
+
+    #[test]
+    fn tasks_fit_the_call_depth_limit() {
+        // every call nests twenty groupings deep, which overflowed the smaller task stacks
+        let source = format!(
+            "fun f(n) {{ if (n > 495) return n; return {}f(n + 1){}; }}\n\
+             var result;\n\
+             fun w() {{ result = f(0); }}\n\
+             spawn w();",
+            "(".repeat(20),
+            ")".repeat(20)
+        );
+        let (interpreter, result) = run(&source);
+
+        assert!(result.is_ok());
+        assert_eq!(global(&interpreter, "result"), Object::Int(496));
+    }
+
+    #[test]
+    fn exit_unwinds_calls_and_tasks() {
+        let (interpreter, result) = run("var a = 1; fun f() { exit(2); } f(); a = 2;");
+
+        assert!(matches!(result, Err(LoxError::Exit(2))));
+        assert_eq!(global(&interpreter, "a"), Object::Int(1));
+
+        let (_, result) = run("fun f() { exit(0); } spawn f(); yield();");
+        assert!(matches!(result, Err(LoxError::Exit(0))));
+
+        let (_, result) = run("exit(256);");
+        assert!(matches!(result, Err(LoxError::Runtime { .. })));
+    }
+
+    #[test]
+    fn args_and_environment() {
+        let (interpreter, result) = run_with(
+            "\
+             setEnv(\"YALIIR_TEST_VARIABLE\", \"set\"); var set = env(\"YALIIR_TEST_VARIABLE\"); \
+             setEnv(\"YALIIR_TEST_VARIABLE\", nil); var unset = env(\"YALIIR_TEST_VARIABLE\");",
+            |interpreter| interpreter.set_args(&["one", "two"]),
+            |_| {},
+        );
+
+        assert!(result.is_ok());
+        assert_eq!(
+            global(&interpreter, "args"),
+            Object::List(Rc::new(vec![
+                Object::String("one".to_owned()),
+                Object::String("two".to_owned())
+            ]))
+        );
+        assert_eq!(
+            global(&interpreter, "set"),
+            Object::String("set".to_owned())
+        );
+        assert_eq!(global(&interpreter, "unset"), Object::Null);
+    }
+
+    #[test]
+    fn fuel_limits_execution() {
+        let limited = |source| {
+            run_with(
+                source,
+                |interpreter| interpreter.set_fuel(Some(100)),
+                |_| {},
+            )
+            .1
+        };
+
+        assert!(
+            matches!(limited("while (true) {}"), Err(LoxError::Error(message)) if message == "Ran out of fuel.")
+        );
+        assert!(limited("setInterval(clock, 1);").is_err());
+    }
+}
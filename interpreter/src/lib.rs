@@ -0,0 +1,23 @@
+mod astprinter;
+mod callable;
+pub mod codes;
+pub mod diagnostic;
+mod environment;
+pub mod error;
+mod event_loop;
+mod expression;
+mod formatter;
+mod function;
+pub mod interpreter;
+pub mod linter;
+pub mod lox;
+mod object;
+pub mod parser;
+mod repl;
+pub mod scanner;
+mod scheduler;
+pub mod span;
+pub mod statement;
+mod suggestion;
+pub mod token;
+pub mod typechecker;
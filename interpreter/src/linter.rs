@@ -0,0 +1,536 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::codes::{self, ErrorCode};
+use crate::diagnostic::{Diagnostic, Diagnostics};
+use crate::expression::{self, Expr};
+use crate::object::Object;
+use crate::span::Label;
+use crate::statement::{self, Pattern, Stmt};
+use crate::token::{Token, TokenType};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Lint {
+    UnusedVariable,
+    UnreachableCode,
+    ConstantCondition,
+    Shadowing,
+    StrayDoc,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 5] = [
+        Lint::UnusedVariable,
+        Lint::UnreachableCode,
+        Lint::ConstantCondition,
+        Lint::Shadowing,
+        Lint::StrayDoc,
+    ];
+
+    // as written in `--allow=` and `--warn=`
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::UnusedVariable => "unused-variable",
+            Lint::UnreachableCode => "unreachable-code",
+            Lint::ConstantCondition => "constant-condition",
+            Lint::Shadowing => "shadowing",
+            Lint::StrayDoc => "stray-doc",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|lint| lint.name() == name)
+    }
+
+    fn code(self) -> &'static ErrorCode {
+        match self {
+            Lint::UnusedVariable => codes::UNUSED_VARIABLE,
+            Lint::UnreachableCode => codes::UNREACHABLE_CODE,
+            Lint::ConstantCondition => codes::CONSTANT_CONDITION,
+            Lint::Shadowing => codes::SHADOWED_VARIABLE,
+            Lint::StrayDoc => codes::STRAY_DOC_COMMENT,
+        }
+    }
+}
+
+struct Local {
+    name: Token,
+    is_param: bool,
+    used: bool,
+}
+
+// Looks for code that is valid but probably not what was meant. Only ever produces warnings, the
+// program runs no matter what is found.
+pub struct Linter {
+    // innermost last, the first scope holds globals which are never reported as unused since
+    // REPL input and other scripts can still read them
+    scopes: Vec<HashMap<String, Local>>,
+    enabled: HashSet<Lint>,
+    warnings: Vec<Diagnostic>,
+}
+
+impl Linter {
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![HashMap::new()],
+            enabled: Lint::ALL.iter().copied().collect(),
+            warnings: vec![],
+        }
+    }
+
+    pub fn set_enabled(&mut self, lint: Lint, enabled: bool) {
+        if enabled {
+            self.enabled.insert(lint);
+        } else {
+            self.enabled.remove(&lint);
+        }
+    }
+
+    // forget the globals seen so far, for a REPL session that starts over
+    pub fn reset(&mut self) {
+        self.scopes = vec![HashMap::new()];
+    }
+
+    pub fn lint(&mut self, statements: &[Stmt], diagnostics: &mut Diagnostics) {
+        self.lint_statements(statements);
+
+        for warning in self.warnings.drain(..) {
+            diagnostics.push(warning);
+        }
+    }
+
+    // what the syntax tree no longer shows, run before `lint` on the tokens it was parsed from
+    pub fn lint_tokens(&mut self, tokens: &[Token], diagnostics: &mut Diagnostics) {
+        // the scanner hands `///` comments to whatever comes next, only functions keep them
+        for token in tokens {
+            if token.doc.is_some() && token.token_type != TokenType::Fun {
+                self.warn(
+                    Lint::StrayDoc,
+                    Diagnostic::warning(
+                        Some(token.span()),
+                        "Doc comment is not followed by a function declaration.",
+                    ),
+                );
+            }
+        }
+
+        for warning in self.warnings.drain(..) {
+            diagnostics.push(warning);
+        }
+    }
+
+    fn warn(&mut self, lint: Lint, warning: Diagnostic) {
+        if self.enabled.contains(&lint) {
+            self.warnings.push(warning.with_code(lint.code()));
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        let scope = match self.scopes.pop() {
+            Some(scope) => scope,
+            None => return,
+        };
+
+        let mut unused = scope
+            .into_values()
+            .filter(|local| !local.used && !local.name.lexeme.starts_with('_'))
+            .collect::<Vec<_>>();
+        unused.sort_by_key(|local| local.name.offset);
+
+        for local in unused {
+            let kind = if local.is_param {
+                "parameter"
+            } else {
+                "variable"
+            };
+
+            self.warn(
+                Lint::UnusedVariable,
+                Diagnostic {
+                    notes: vec![format!(
+                        "if this is intentional, prefix it with an underscore: '_{}'",
+                        local.name.lexeme
+                    )],
+                    ..Diagnostic::warning(
+                        Some(local.name.span()),
+                        format!("Unused {} '{}'.", kind, local.name.lexeme),
+                    )
+                },
+            );
+        }
+    }
+
+    fn declare(&mut self, name: &Token, is_param: bool) {
+        let depth = self.scopes.len() - 1;
+
+        // redeclaring in the same scope replaces the binding, that is not shadowing
+        if depth > 0 && !self.scopes[depth].contains_key(&name.lexeme) {
+            let outer = self.scopes[..depth]
+                .iter()
+                .rev()
+                .find_map(|scope| scope.get(&name.lexeme));
+
+            if let Some(outer) = outer {
+                let warning = Diagnostic {
+                    labels: vec![Label::new(outer.name.span(), "previously declared here")],
+                    ..Diagnostic::warning(
+                        Some(name.span()),
+                        format!("'{}' shadows a variable from an outer scope.", name.lexeme),
+                    )
+                };
+
+                self.warn(Lint::Shadowing, warning);
+            }
+        }
+
+        self.scopes[depth].insert(
+            name.lexeme.clone(),
+            Local {
+                name: name.clone(),
+                is_param,
+                used: false,
+            },
+        );
+    }
+
+    fn bind_pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Name(name) => self.declare(name, false),
+            Pattern::List { elements, .. } => {
+                for element in elements {
+                    self.bind_pattern(element);
+                }
+            }
+            Pattern::Fields { names, .. } => {
+                for name in names {
+                    self.declare(name, false);
+                }
+            }
+        }
+    }
+
+    fn lint_statements(&mut self, statements: &[Stmt]) {
+        let mut returned = false;
+
+        for statement in statements {
+            if returned {
+                if let Some(span) = statement.span() {
+                    self.warn(
+                        Lint::UnreachableCode,
+                        Diagnostic::warning(Some(span), "Unreachable code after return."),
+                    );
+
+                    // one warning covers the rest of the block
+                    returned = false;
+                }
+            }
+
+            self.lint_stmt(statement);
+
+            if matches!(statement, Stmt::Return { .. }) {
+                returned = true;
+            }
+        }
+    }
+
+    fn lint_expr(&mut self, expr: &Expr) {
+        expr.accept(self)
+    }
+
+    fn lint_stmt(&mut self, stmt: &Stmt) {
+        stmt.accept(self)
+    }
+}
+
+impl expression::Visitor<()> for Linter {
+    fn visit_assign(&mut self, _name: &Token, value: &Expr) {
+        self.lint_expr(value);
+    }
+
+    fn visit_binary(&mut self, left: &Expr, _operator: &Token, right: &Expr) {
+        self.lint_expr(left);
+        self.lint_expr(right);
+    }
+
+    fn visit_call(&mut self, callee: &Expr, _paren: &Token, arguments: &[Expr]) {
+        self.lint_expr(callee);
+
+        for argument in arguments {
+            self.lint_expr(argument);
+        }
+    }
+
+    fn visit_get(&mut self, object: &Expr, _name: &Token) {
+        self.lint_expr(object);
+    }
+
+    fn visit_grouping(&mut self, expression: &Expr) {
+        self.lint_expr(expression);
+    }
+
+    fn visit_list(&mut self, _bracket: &Token, elements: &[Expr]) {
+        for element in elements {
+            self.lint_expr(element);
+        }
+    }
+
+    fn visit_literal(&mut self, _object: &Object) {}
+
+    fn visit_logical(&mut self, left: &Expr, _operator: &Token, right: &Expr) {
+        self.lint_expr(left);
+        self.lint_expr(right);
+    }
+
+    fn visit_set(&mut self, object: &Expr, _token: &Token, value: &Expr) {
+        self.lint_expr(object);
+        self.lint_expr(value);
+    }
+
+    fn visit_super(&mut self, _keyword: &Token, _method: &Token) {}
+
+    fn visit_this(&mut self, _keyword: &Token) {}
+
+    fn visit_unary(&mut self, _operator: &Token, right: &Expr) {
+        self.lint_expr(right);
+    }
+
+    fn visit_variable(&mut self, name: &Token) {
+        let local = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(&name.lexeme));
+
+        if let Some(local) = local {
+            local.used = true;
+        }
+    }
+}
+
+impl statement::Visitor<()> for Linter {
+    fn visit_assert(
+        &mut self,
+        _keyword: &Token,
+        condition: &Expr,
+        message: &Option<Expr>,
+        _source: &str,
+    ) {
+        self.lint_expr(condition);
+
+        if let Some(message) = message {
+            self.lint_expr(message);
+        }
+    }
+
+    fn visit_block(&mut self, statements: &[Stmt]) {
+        self.begin_scope();
+        self.lint_statements(statements);
+        self.end_scope();
+    }
+
+    fn visit_destructure(&mut self, pattern: &Pattern, initializer: &Expr) {
+        self.lint_expr(initializer);
+        self.bind_pattern(pattern);
+    }
+
+    fn visit_expression(&mut self, value: &Expr) {
+        self.lint_expr(value);
+    }
+
+    fn visit_function(
+        &mut self,
+        name: &Token,
+        params: &[Token],
+        _param_types: &[Option<Token>],
+        _return_type: &Option<Token>,
+        body: &[Stmt],
+        _doc: &Option<String>,
+    ) {
+        // declared before the body so recursive calls count as uses
+        self.declare(name, false);
+
+        self.begin_scope();
+
+        for param in params {
+            self.declare(param, true);
+        }
+
+        self.lint_statements(body);
+        self.end_scope();
+    }
+
+    fn visit_if(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: &Option<Box<Stmt>>) {
+        let mut literal = condition;
+
+        while let Expr::Grouping { expression, .. } = literal {
+            literal = expression;
+        }
+
+        if let Expr::Literal { object, .. } = literal {
+            self.warn(
+                Lint::ConstantCondition,
+                Diagnostic::warning(
+                    Some(condition.span()),
+                    format!("This condition is always {}.", object.is_truthy()),
+                ),
+            );
+        }
+
+        self.lint_expr(condition);
+        self.lint_stmt(then_branch);
+
+        if let Some(else_branch) = else_branch {
+            self.lint_stmt(else_branch);
+        }
+    }
+
+    fn visit_multi_assign(&mut self, _targets: &[Token], values: &[Expr]) {
+        for value in values {
+            self.lint_expr(value);
+        }
+    }
+
+    fn visit_print(&mut self, value: &Expr) {
+        self.lint_expr(value);
+    }
+
+    fn visit_return(&mut self, _keyword: &Token, value: &Option<Expr>) {
+        if let Some(value) = value {
+            self.lint_expr(value);
+        }
+    }
+
+    fn visit_spawn(&mut self, _keyword: &Token, callee: &Expr, paren: &Token, arguments: &[Expr]) {
+        expression::Visitor::visit_call(self, callee, paren, arguments);
+    }
+
+    fn visit_var(
+        &mut self,
+        name: &Token,
+        _type_annotation: &Option<Token>,
+        initializer: &Option<Expr>,
+    ) {
+        if let Some(initializer) = initializer {
+            self.lint_expr(initializer);
+        }
+
+        self.declare(name, false);
+    }
+
+    fn visit_while(&mut self, condition: &Expr, body: &Stmt) {
+        self.lint_expr(condition);
+        self.lint_stmt(body);
+    }
+}
+
+impl Default for Linter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Lint, Linter};
+
+    use crate::diagnostic::{Diagnostics, Severity};
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn lint_with(linter: &mut Linter, source: &str) -> Vec<String> {
+        let tokens = Scanner::new(source)
+            .scan_tokens(&mut Diagnostics::default())
+            .clone();
+        let statements = Parser::new(&tokens).parse(&mut Diagnostics::default());
+
+        let mut diagnostics = Diagnostics::default();
+        linter.lint_tokens(&tokens, &mut diagnostics);
+        linter.lint(&statements, &mut diagnostics);
+
+        diagnostics
+            .drain()
+            .map(|diagnostic| {
+                assert_eq!(diagnostic.severity, Severity::Warning);
+
+                diagnostic.message
+            })
+            .collect()
+    }
+
+    fn lint(source: &str) -> Vec<String> {
+        lint_with(&mut Linter::new(), source)
+    }
+
+    #[test]
+    fn unused_locals_and_params() {
+        assert_eq!(
+            lint("var global = 1; fun f(a, b, _c) { var d = a; var e; e = 1; { var g = d; } }"),
+            vec![
+                "Unused variable 'g'.",
+                "Unused parameter 'b'.",
+                "Unused variable 'e'.",
+            ]
+        );
+        assert!(lint("fun f(n) { if (n > 0) return f(n - 1); return n; } f(1);").is_empty());
+    }
+
+    #[test]
+    fn unreachable_after_return() {
+        assert_eq!(
+            lint("fun f() { return 1; print 2; print 3; }"),
+            vec!["Unreachable code after return."]
+        );
+    }
+
+    #[test]
+    fn constant_conditions() {
+        assert_eq!(
+            lint("var a = 1; if (a) print 1; if ((nil)) print 2; if (\"s\") print 3;"),
+            vec![
+                "This condition is always false.",
+                "This condition is always true."
+            ]
+        );
+    }
+
+    #[test]
+    fn shadowing() {
+        assert_eq!(
+            lint("var a = 1; var a = 2; fun f(a) { print a; { var a = 3; print a; } }"),
+            vec![
+                "'a' shadows a variable from an outer scope.",
+                "'a' shadows a variable from an outer scope.",
+            ]
+        );
+    }
+
+    #[test]
+    fn lints_can_be_disabled() {
+        let mut linter = Linter::new();
+        linter.set_enabled(Lint::UnusedVariable, false);
+        linter.set_enabled(Lint::ConstantCondition, false);
+
+        assert!(lint_with(&mut linter, "fun f(a) { var b; if (true) return; }").is_empty());
+        assert_eq!(Lint::from_name("shadowing"), Some(Lint::Shadowing));
+        assert_eq!(Lint::from_name("nope"), None);
+    }
+
+    #[test]
+    fn stray_doc_comments() {
+        assert_eq!(
+            lint("/// Adds.\nfun add(a, b) { return a + b; }\n/// Count.\nvar count = 0;\nprint 1; /// end"),
+            vec![
+                "Doc comment is not followed by a function declaration.",
+                "Doc comment is not followed by a function declaration.",
+            ]
+        );
+
+        let mut linter = Linter::new();
+        linter.set_enabled(Lint::StrayDoc, false);
+
+        assert!(lint_with(&mut linter, "/// Count.\nvar count = 0;").is_empty());
+    }
+}
@@ -1,127 +1,711 @@
-use std::error::Error;
+use std::env;
 use std::fs;
-use std::io;
-use std::path::Path;
-use std::process;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
 use anyhow::Context;
+use rustyline::error::ReadlineError;
+use rustyline::history::FileHistory;
+use rustyline::{CompletionType, Config, Editor};
+use thiserror::Error;
 
+use crate::astprinter::AstPrinter;
+use crate::codes;
+use crate::diagnostic::{Diagnostic, Diagnostics};
 use crate::error::LoxError;
+use crate::formatter;
 use crate::interpreter::Interpreter;
+use crate::linter::{Lint, Linter};
+use crate::object::Object;
 use crate::parser::Parser;
+use crate::repl::{self, Command, ReplHelper};
 use crate::scanner::Scanner;
-use crate::token::{Token, TokenType};
+use crate::statement::Stmt;
+use crate::token::Token;
+use crate::typechecker::{Type, TypeChecker};
+
+/// Why a file could not be run, checked or formatted. Problems with the program itself were
+/// already reported as diagnostics by the time this is returned.
+#[derive(Debug, Error)]
+pub enum Failure {
+    #[error("{0:#}")]
+    Read(anyhow::Error),
+
+    #[error("{0:#}")]
+    Write(anyhow::Error),
+
+    // the REPL could not read a line
+    #[error("{0}")]
+    Terminal(#[from] ReadlineError),
+
+    #[error("the program has errors")]
+    InvalidSource,
+
+    #[error("the program failed while running")]
+    Runtime,
+
+    #[error("the program exited with code {0}")]
+    Exit(exitcode::ExitCode),
+}
+
+impl Failure {
+    pub fn exit_code(&self) -> exitcode::ExitCode {
+        match self {
+            Failure::Read(_) => exitcode::NOINPUT,
+            Failure::Write(_) => exitcode::CANTCREAT,
+            Failure::Terminal(_) => exitcode::IOERR,
+            Failure::InvalidSource => exitcode::DATAERR,
+            Failure::Runtime => exitcode::SOFTWARE,
+            Failure::Exit(code) => *code,
+        }
+    }
+
+    // whether the diagnostics already said what went wrong
+    pub fn is_reported(&self) -> bool {
+        matches!(
+            self,
+            Failure::InvalidSource | Failure::Runtime | Failure::Exit(_)
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorFormat {
+    Human,
+    // one JSON object per line, for editors and CI
+    Json,
+}
 
 pub struct Lox {
     interpreter: Interpreter,
-    had_error: bool,
+    checker: TypeChecker,
+    linter: Linter,
+    // everything run so far, REPL input accumulates so old spans can still be shown
+    source: String,
+    // path of the script being run, None for the REPL
+    file: Option<String>,
+    error_format: ErrorFormat,
+    // REPL input that ran without errors, in order, for `:save`
+    session: Vec<String>,
+    // kept to set up the interpreter again on `:reset`
+    virtual_clock: bool,
+    // echo each chunk of source before running it, for debugging the interpreter
+    verbose: bool,
     had_runtime_error: bool,
+    // set once the program calls `exit(code)`
+    exit_code: Option<exitcode::ExitCode>,
 }
 
 impl Lox {
     pub fn new() -> Self {
         Self {
-            had_error: false,
             had_runtime_error: false,
+            exit_code: None,
             interpreter: Interpreter::new(),
+            checker: TypeChecker::new(),
+            linter: Linter::new(),
+            source: String::new(),
+            file: None,
+            error_format: ErrorFormat::Human,
+            verbose: false,
+            session: Vec::new(),
+            virtual_clock: false,
         }
     }
 
-    pub fn run_file(&mut self, source_file: &Path) -> anyhow::Result<()> {
-        let contents = fs::read_to_string(source_file)
-            .with_context(|| format!("could not read file `{}`", source_file.to_string_lossy()))?;
+    pub fn set_asserts_enabled(&mut self, enabled: bool) {
+        self.interpreter.asserts_enabled = enabled;
+    }
 
-        if let Err(err) = self.run(&contents) {
-            eprintln!("Error running file: {}", err);
+    pub fn set_lint_enabled(&mut self, lint: Lint, enabled: bool) {
+        self.linter.set_enabled(lint, enabled);
+    }
 
-            process::exit(exitcode::DATAERR);
-        };
+    pub fn set_error_format(&mut self, format: ErrorFormat) {
+        self.error_format = format;
+    }
+
+    pub fn set_args(&mut self, args: &[&str]) {
+        self.interpreter.set_args(args);
+    }
+
+    pub fn set_verbose(&mut self, verbose: bool) {
+        self.verbose = verbose;
+    }
+
+    pub fn use_virtual_clock(&mut self) {
+        self.virtual_clock = true;
+        self.interpreter.use_virtual_clock();
+    }
+
+    pub fn run_file(&mut self, source_file: &Path) -> Result<(), Failure> {
+        let contents = self.read_file(source_file)?;
+
+        self.run_script(&contents)
+    }
+
+    // `yaliir -`
+    pub fn run_stdin(&mut self) -> Result<(), Failure> {
+        let mut contents = String::new();
+
+        io::stdin()
+            .read_to_string(&mut contents)
+            .context("could not read stdin")
+            .map_err(Failure::Read)?;
+
+        self.file = Some("<stdin>".to_owned());
+
+        self.run_script(&contents)
+    }
+
+    // `yaliir -e 'print 1;'`
+    pub fn run_inline(&mut self, source: &str) -> Result<(), Failure> {
+        self.file = Some("<command line>".to_owned());
+
+        self.run_script(source)
+    }
+
+    fn run_script(&mut self, contents: &str) -> Result<(), Failure> {
+        // timers, tasks and I/O started by the script keep it alive after the last statement
+        self.run(contents, |_| {})
+            .and_then(|_| {
+                if self.had_runtime_error || self.exit_code.is_some() {
+                    return Ok(());
+                }
+
+                let mut diagnostics = Diagnostics::default();
+
+                let result = self.interpreter.run_event_loop();
+                let result = self.handle_runtime_result(result, &mut diagnostics);
+
+                self.emit(&mut diagnostics);
+
+                result
+            })
+            .map_err(|_| Failure::InvalidSource)?;
+
+        if let Some(code) = self.exit_code {
+            return Err(Failure::Exit(code));
+        }
 
-        // highly inconsistent with had_error handling
         if self.had_runtime_error {
-            process::exit(exitcode::SOFTWARE);
+            return Err(Failure::Runtime);
         }
 
         Ok(())
     }
 
-    pub fn run_prompt(&mut self) -> Result<(), Box<dyn Error>> {
+    // scan, parse and type check without running anything
+    pub fn check_file(&mut self, source_file: &Path) -> Result<(), Failure> {
+        let contents = self.read_file(source_file)?;
+
+        let mut diagnostics = Diagnostics::default();
+
+        let result = self.analyze(&contents, &mut diagnostics);
+
+        self.emit(&mut diagnostics);
+
+        result.map(|_| ()).map_err(|_| Failure::InvalidSource)
+    }
+
+    // prints the formatted file, or replaces it with `write`. Only valid code is formatted.
+    pub fn format_file(&mut self, source_file: &Path, write: bool) -> Result<(), Failure> {
+        let contents = self.read_file(source_file)?;
+
+        let mut diagnostics = Diagnostics::default();
+
+        let tokens = self
+            .scan(&contents, &mut diagnostics)
+            .ok()
+            .filter(|tokens| {
+                Parser::new(tokens).parse(&mut diagnostics);
+
+                !diagnostics.has_errors()
+            });
+
+        self.emit(&mut diagnostics);
+
+        let tokens = tokens.ok_or(Failure::InvalidSource)?;
+
+        let formatted = formatter::format(&contents, &tokens);
+
+        if write {
+            fs::write(source_file, formatted)
+                .with_context(|| {
+                    format!("could not write file `{}`", source_file.to_string_lossy())
+                })
+                .map_err(Failure::Write)
+        } else {
+            print!("{}", formatted);
+
+            Ok(())
+        }
+    }
+
+    pub fn print_tokens(&mut self, source_file: &Path) -> Result<(), Failure> {
+        let contents = self.read_file(source_file)?;
+
+        let mut diagnostics = Diagnostics::default();
+
+        let tokens = self.scan(&contents, &mut diagnostics);
+
+        self.emit(&mut diagnostics);
+
+        for token in tokens.as_ref().map_err(|_| Failure::InvalidSource)? {
+            println!("{}", describe_token(token));
+        }
+
+        Ok(())
+    }
+
+    pub fn print_ast(&mut self, source_file: &Path) -> Result<(), Failure> {
+        let contents = self.read_file(source_file)?;
+
+        let mut diagnostics = Diagnostics::default();
+
+        let statements = self.parse(&contents, &mut diagnostics);
+
+        self.emit(&mut diagnostics);
+
+        for statement in statements.as_ref().map_err(|_| Failure::InvalidSource)? {
+            println!("{}", AstPrinter.print_stmt(statement));
+        }
+
+        Ok(())
+    }
+
+    fn read_file(&mut self, source_file: &Path) -> Result<String, Failure> {
+        let contents = fs::read_to_string(source_file)
+            .with_context(|| format!("could not read file `{}`", source_file.to_string_lossy()))
+            .map_err(Failure::Read)?;
+
+        self.file = Some(source_file.to_string_lossy().into_owned());
+
+        Ok(contents)
+    }
+
+    pub fn run_prompt(&mut self) -> Result<(), Failure> {
+        let config = Config::builder()
+            .completion_type(CompletionType::List)
+            .build();
+        let mut editor = Editor::<ReplHelper, FileHistory>::with_config(config)?;
+        editor.set_helper(Some(ReplHelper::default()));
+        let mut history = history_path();
+
+        if let Some(path) = &history {
+            // there is no history yet on the first run
+            let _ = editor.load_history(path);
+        }
+
+        let mut input = String::new();
+
         loop {
-            let mut line = String::new();
+            if let Some(code) = self.exit_code {
+                return Err(Failure::Exit(code));
+            }
 
-            eprint!("> ");
+            let prompt = if input.is_empty() { "> " } else { "... " };
 
-            if io::stdin()
-                .read_line(&mut line)
-                .with_context(|| "unable to read stdin".to_owned())?
-                == 0
-            {
-                break;
+            if let Some(helper) = editor.helper_mut() {
+                helper.update(&self.interpreter.bindings());
+            }
+
+            let line = match editor.readline(prompt) {
+                Ok(line) => line,
+                // Ctrl-C throws away what was typed so far, Ctrl-D quits
+                Err(ReadlineError::Interrupted) => {
+                    input.clear();
+                    continue;
+                }
+                Err(ReadlineError::Eof) => break,
+                Err(err) => return Err(Failure::Terminal(err)),
+            };
+
+            if input.is_empty() {
+                if let Some(command) = repl::Command::parse(&line) {
+                    // only fails for a history that is kept in a file as it grows, not here
+                    let _ = editor.add_history_entry(line.trim());
+
+                    if let Err(err) = command
+                        .map_err(anyhow::Error::msg)
+                        .and_then(|command| self.run_command(command))
+                    {
+                        eprintln!("Error: {:#}", err);
+                    }
+
+                    continue;
+                }
             }
 
-            let _ = self.run(&line);
-            self.had_error = false;
+            input.push_str(&line);
+            input.push('\n');
+
+            if input.trim().is_empty() {
+                input.clear();
+                continue;
+            }
+
+            // an empty line runs incomplete input anyway to show what is missing
+            if !line.trim().is_empty() && !repl::is_complete(&input) {
+                match repl::terminate_expression(&input) {
+                    Some(terminated) => input = terminated,
+                    None => continue,
+                }
+            }
+
+            let _ = editor.add_history_entry(input.trim_end());
+
+            save_history(&mut editor, &mut history);
+
+            self.run_input(&input, show_value);
+
+            input.clear();
         }
 
-        eprintln!();
+        save_history(&mut editor, &mut history);
 
         Ok(())
     }
 
-    fn run(&mut self, source: &str) -> Result<(), LoxError> {
-        eprintln!("running source:\n{}", source);
+    // runs REPL input, remembering it for `:save` when it ran without errors
+    fn run_input(&mut self, source: &str, show: impl FnMut(&Object)) {
+        self.had_runtime_error = false;
 
-        let mut scanner = Scanner::new(source);
-        let tokens = scanner.scan_tokens(self);
+        if self.run(source, show).is_ok() && !self.had_runtime_error {
+            self.session.push(source.to_owned());
+        }
+    }
 
-        if self.had_error {
+    fn run_command(&mut self, command: Command) -> anyhow::Result<()> {
+        match command {
+            Command::Help => {
+                for (name, argument, description) in repl::COMMANDS {
+                    println!("{:<14} {}", format!("{} {}", name, argument), description);
+                }
+            }
+            Command::Env => {
+                let mut bindings = self.interpreter.bindings();
+                bindings.sort_by(|(left, _), (right, _)| left.cmp(right));
+
+                for (name, value) in bindings {
+                    println!("{} = {}", name, value);
+                }
+            }
+            Command::Type(source) => {
+                let source = expression_source(source)?;
+                let mut diagnostics = Diagnostics::default();
+
+                let statements = self.parse(&source, &mut diagnostics);
+
+                self.emit(&mut diagnostics);
+
+                let value = match statements.as_deref() {
+                    Ok([Stmt::Expression { value }]) => value,
+                    Ok(_) => anyhow::bail!("`{}` is not a single expression", source),
+                    Err(_) => return Ok(()),
+                };
+
+                let inferred = self.checker.infer(value, &mut diagnostics);
+
+                self.emit(&mut diagnostics);
+
+                if inferred != Type::Any || !repl::is_pure(value) {
+                    println!("{}", inferred);
+
+                    return Ok(());
+                }
+
+                // unannotated code has a type only once it runs, which changes nothing here
+                let mut value_type = Type::Nil;
+
+                self.had_runtime_error = false;
+
+                if self
+                    .run(&source, |value| value_type = Type::of_object(value))
+                    .is_ok()
+                    && !self.had_runtime_error
+                {
+                    println!("{}", value_type);
+                }
+            }
+            Command::Ast(source) => {
+                let source = expression_source(source)?;
+                let mut diagnostics = Diagnostics::default();
+
+                let statements = self.parse(&source, &mut diagnostics);
+
+                self.emit(&mut diagnostics);
+
+                for statement in statements.iter().flatten() {
+                    if let Stmt::Expression { value } = statement {
+                        println!("{}", AstPrinter.print(value));
+                    }
+                }
+            }
+            Command::Tokens(source) => {
+                let mut diagnostics = Diagnostics::default();
+
+                let tokens = self.scan(source, &mut diagnostics);
+
+                self.emit(&mut diagnostics);
+
+                for token in tokens.iter().flatten() {
+                    println!("{}", describe_token(token));
+                }
+            }
+            Command::Load(path) => {
+                let contents = fs::read_to_string(path)
+                    .with_context(|| format!("could not read file `{}`", path))?;
+
+                self.run_input(&contents, |_| {});
+            }
+            Command::Reset => {
+                let asserts_enabled = self.interpreter.asserts_enabled;
+
+                self.interpreter = Interpreter::new();
+                self.interpreter.asserts_enabled = asserts_enabled;
+
+                if self.virtual_clock {
+                    self.interpreter.use_virtual_clock();
+                }
+
+                self.checker = TypeChecker::new();
+                self.linter.reset();
+                self.session.clear();
+            }
+            Command::Time(source) => {
+                let source =
+                    repl::terminate_expression(source).unwrap_or_else(|| source.to_owned());
+                let start = Instant::now();
+
+                self.run_input(&source, show_value);
+
+                println!("took {:?}", start.elapsed());
+            }
+            Command::Save(path) => {
+                fs::write(path, self.session.concat())
+                    .with_context(|| format!("could not write file `{}`", path))?;
+
+                println!("saved {} inputs to {}", self.session.len(), path);
+            }
+        }
+
+        Ok(())
+    }
+
+    // `show` gets the values of top level expression statements, the REPL prints them
+    fn run(&mut self, source: &str, show: impl FnMut(&Object)) -> Result<(), LoxError> {
+        if self.verbose {
+            eprintln!("running source:\n{}", source);
+        }
+
+        let mut diagnostics = Diagnostics::default();
+
+        let statements = self.analyze(source, &mut diagnostics);
+
+        // warnings come out before any output of the program
+        self.emit(&mut diagnostics);
+
+        let result = statements.and_then(|statements| {
+            let result = self.interpreter.interpret_showing(&statements, show);
+
+            self.handle_runtime_result(result, &mut diagnostics)
+        });
+
+        self.emit(&mut diagnostics);
+
+        result
+    }
+
+    fn handle_runtime_result(
+        &mut self,
+        result: Result<(), LoxError>,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<(), LoxError> {
+        let diagnostic = match result {
+            Ok(()) => return Ok(()),
+            Err(LoxError::Runtime {
+                message,
+                token,
+                code,
+                labels,
+                mut notes,
+            }) => {
+                notes.extend(
+                    self.interpreter
+                        .take_trace()
+                        .iter()
+                        .map(|frame| format!("in {}", frame)),
+                );
+
+                Diagnostic {
+                    labels,
+                    notes,
+                    ..Diagnostic::error(Some(token.span()), message).with_code(code)
+                }
+            }
+            Err(LoxError::Deadlock(message)) => {
+                Diagnostic::error(None, message).with_code(codes::DEADLOCK)
+            }
+            Err(LoxError::Exit(code)) => {
+                self.exit_code = Some(code);
+
+                return Ok(());
+            }
+            // raised once the script is done and nothing points at a single location
+            Err(LoxError::Error(message)) => Diagnostic::error(None, message),
+            Err(err) => return Err(err),
+        };
+
+        diagnostics.push(diagnostic);
+
+        self.had_runtime_error = true;
+
+        Ok(())
+    }
+
+    fn scan(
+        &mut self,
+        source: &str,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<Vec<Token>, LoxError> {
+        if !self.source.is_empty() && !self.source.ends_with('\n') {
+            self.source.push('\n');
+        }
+
+        let line = self.source.matches('\n').count() as i32 + 1;
+        let offset = self.source.len();
+
+        self.source.push_str(source);
+
+        let mut scanner = Scanner::with_position(source, line, offset);
+        let tokens = scanner.scan_tokens(diagnostics).clone();
+
+        if diagnostics.has_errors() {
             return Err(LoxError::Error(
                 "encountered error(s) during scanning".to_owned(),
             ));
         }
 
+        Ok(tokens)
+    }
+
+    fn parse(
+        &mut self,
+        source: &str,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<Vec<Stmt>, LoxError> {
+        let tokens = self.scan(source, diagnostics)?;
+
+        Self::parse_tokens(&tokens, diagnostics)
+    }
+
+    fn parse_tokens(
+        tokens: &[Token],
+        diagnostics: &mut Diagnostics,
+    ) -> Result<Vec<Stmt>, LoxError> {
         let mut parser = Parser::new(tokens);
-        let statements = parser.parse(self);
+        let statements = parser.parse(diagnostics);
 
-        if self.had_error {
+        if diagnostics.has_errors() {
             return Err(LoxError::Error(
                 "encountered error(s) during parsing".to_owned(),
             ));
         }
 
-        if let Err(err) = self.interpreter.interpret(&statements) {
-            match err {
-                LoxError::Runtime { message, token } => {
-                    self.runtime_error(&message, &token);
-                }
-                e => return Err(e),
-            }
-        };
-
-        Ok(())
+        Ok(statements)
     }
 
-    pub fn error(&mut self, line: i32, message: &str) {
-        self.report(line, "", message);
-    }
+    fn analyze(
+        &mut self,
+        source: &str,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<Vec<Stmt>, LoxError> {
+        let tokens = self.scan(source, diagnostics)?;
+        let statements = Self::parse_tokens(&tokens, diagnostics)?;
 
-    pub fn parser_error(&mut self, token: &Token, message: &str) {
-        if token.token_type == TokenType::Eof {
-            self.report(token.line, "at end", message);
-        } else {
-            self.report(token.line, &format!("at '{}'", token.lexeme), message);
+        self.checker.check(&statements, diagnostics);
+
+        if diagnostics.has_errors() {
+            return Err(LoxError::Error(
+                "encountered error(s) during type checking".to_owned(),
+            ));
         }
+
+        // only warnings, they never stop the program from running
+        self.linter.lint_tokens(&tokens, diagnostics);
+        self.linter.lint(&statements, diagnostics);
+
+        Ok(statements)
     }
 
-    pub fn runtime_error(&mut self, message: &str, token: &Token) {
-        eprintln!("{}\n[line {}]", message, token.line);
+    fn emit(&self, diagnostics: &mut Diagnostics) {
+        let mut first_code = None;
 
-        self.had_runtime_error = true;
+        for diagnostic in diagnostics.drain() {
+            if diagnostic.is_error() {
+                first_code = first_code.or(diagnostic.code);
+            }
+
+            match self.error_format {
+                ErrorFormat::Human => {
+                    eprintln!("{}", diagnostic.render(&self.source, self.file.as_deref()))
+                }
+                ErrorFormat::Json => eprintln!("{}", diagnostic.to_json(self.file.as_deref())),
+            }
+        }
+
+        if let (ErrorFormat::Human, Some(code)) = (self.error_format, first_code) {
+            eprintln!(
+                "For more information about this error, try `yaliir --explain {}`.",
+                code
+            );
+        }
     }
+}
+
+// `1:5 Identifier name`
+fn describe_token(token: &Token) -> String {
+    format!(
+        "{}:{} {:?} {}",
+        token.line, token.column, token.token_type, token.lexeme
+    )
+}
+
+fn show_value(value: &Object) {
+    println!("=> {}", value);
+}
+
+// source of an expression statement for commands like `:type 1 + 2`
+fn expression_source(source: &str) -> anyhow::Result<String> {
+    repl::terminate_expression(source)
+        .ok_or_else(|| anyhow::anyhow!("`{}` is not an expression", source))
+}
 
-    fn report(&mut self, line: i32, place: &str, message: &str) {
-        eprintln!("[line {}] Error {}: {}", line, place, message);
+// kept in the home directory like the history of most shells
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| Path::new(&home).join(".yaliir_history"))
+}
+
+// The history is a convenience, when it can't be written the session goes on without it after
+// a single warning
+fn save_history(editor: &mut Editor<ReplHelper, FileHistory>, history: &mut Option<PathBuf>) {
+    if let Some(path) = history {
+        if let Err(err) = editor.save_history(path) {
+            eprintln!(
+                "Warning: could not save history to `{}`: {}",
+                path.display(),
+                err
+            );
+
+            *history = None;
+        }
+    }
+}
 
-        self.had_error = true;
+impl Default for Lox {
+    fn default() -> Self {
+        Self::new()
     }
 }
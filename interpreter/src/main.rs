@@ -1,42 +1,205 @@
-// mod astprinter;
-mod callable;
-mod environment;
-mod error;
-mod expression;
-mod function;
-mod interpreter;
-mod lox;
-mod object;
-mod parser;
-mod scanner;
-mod statement;
-mod token;
-
-use std::cmp::Ordering;
 use std::env;
-use std::error::Error;
 use std::path::Path;
 use std::process;
+use std::thread;
 
-use lox::Lox;
+use interpreter::codes;
+use interpreter::interpreter::STACK_SIZE;
+use interpreter::linter::Lint;
+use interpreter::lox::{ErrorFormat, Failure, Lox};
 
-fn main() -> Result<(), Box<dyn Error>> {
+const USAGE: &str = "\
+Usage: yaliir [OPTIONS] [COMMAND]
+       yaliir [OPTIONS] FILE [ARGS]...
+       yaliir [OPTIONS] -e CODE [ARGS]...
+
+Arguments after the script are in its `args` list. A FILE of `-` reads the script from stdin.
+
+Commands:
+  run FILE [ARGS]...  run a script, the default when a file is given
+  repl                start the interactive prompt, the default without arguments
+  check FILE          scan, parse and type check a script without running it
+  fmt FILE            print a script formatted, `--write` replaces the file instead
+  tokens FILE         print the tokens a script is scanned into
+  ast FILE            print the syntax trees a script is parsed into
+
+Options:
+  --no-asserts                skip `assert` statements
+  --virtual-clock             fire timers without waiting for real time to pass
+  --verbose                   echo source before running it
+  --error-format=human|json   how errors and warnings are printed
+  --allow=LINT, --warn=LINT   turn a lint or `all` of them off or on
+  --explain CODE              describe an error code like E0012
+  -e CODE                     run CODE instead of a file
+  -h, --help                  print this help
+  -V, --version               print the version";
+
+const COMMANDS: &[&str] = &["run", "repl", "check", "fmt", "tokens", "ast"];
+
+fn main() {
+    let interpreter = thread::Builder::new().stack_size(STACK_SIZE).spawn(run);
+
+    match interpreter.map(|handle| handle.join()) {
+        Ok(Ok(code)) => process::exit(code),
+        // the panic message is already printed
+        Ok(Err(_)) => process::exit(exitcode::SOFTWARE),
+        Err(err) => {
+            eprintln!("Error: could not start interpreter thread: {}", err);
+
+            process::exit(exitcode::OSERR);
+        }
+    }
+}
+
+fn run() -> exitcode::ExitCode {
     let mut lox = Lox::new();
 
-    let argv = env::args().collect::<Vec<String>>();
+    let mut write = false;
+    let mut inline = None;
+    let mut positional: Vec<String> = vec![];
+
+    let mut raw_args = env::args().skip(1);
+
+    while let Some(arg) = raw_args.next() {
+        match &arg[..] {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
 
-    match argv.len().cmp(&2) {
-        Ordering::Equal => {
-            let source_file = Path::new(&argv[1]);
-            lox.run_file(&source_file)?;
+                return exitcode::OK;
+            }
+            "-V" | "--version" => {
+                println!("yaliir {}", env!("CARGO_PKG_VERSION"));
+
+                return exitcode::OK;
+            }
+            "--explain" => explain(raw_args.next()),
+            "-e" => {
+                inline = Some(
+                    raw_args
+                        .next()
+                        .unwrap_or_else(|| usage("`-e` needs code to run")),
+                );
+
+                // the rest are arguments for the code
+                positional.extend(&mut raw_args);
+            }
+            "--write" => write = true,
+            "--no-asserts" => lox.set_asserts_enabled(false),
+            "--virtual-clock" => lox.use_virtual_clock(),
+            "--verbose" => lox.set_verbose(true),
+            "--error-format=human" => lox.set_error_format(ErrorFormat::Human),
+            "--error-format=json" => lox.set_error_format(ErrorFormat::Json),
+            _ if arg.starts_with("--allow=") || arg.starts_with("--warn=") => {
+                let (flag, name) = arg.split_once('=').unwrap_or_default();
+
+                for lint in lints(name) {
+                    lox.set_lint_enabled(lint, flag == "--warn");
+                }
+            }
+            _ if arg.starts_with('-') && arg != "-" => usage(&format!("unknown option '{}'", arg)),
+            _ => {
+                positional.push(arg);
+
+                // everything after the script belongs to it, options included
+                let script_given = match positional[0].as_str() {
+                    "run" => positional.len() == 2,
+                    command => !COMMANDS.contains(&command),
+                };
+
+                if script_given {
+                    positional.extend(&mut raw_args);
+                }
+            }
         }
-        Ordering::Less => lox.run_prompt()?,
-        Ordering::Greater => {
-            eprintln!("Usage: yaliir [script]");
+    }
+
+    let positional = positional.iter().map(String::as_str).collect::<Vec<_>>();
+
+    if write && positional.first() != Some(&"fmt") {
+        usage("`--write` only works with `fmt`");
+    }
+
+    let result = match (inline, &positional[..]) {
+        (Some(code), args) => {
+            lox.set_args(args);
+
+            lox.run_inline(&code)
+        }
+        (None, []) | (None, ["repl"]) => lox.run_prompt(),
+        (None, ["run", file, args @ ..]) => run_script(&mut lox, file, args),
+        (None, ["check", file]) => lox.check_file(Path::new(file)),
+        (None, ["fmt", file]) => lox.format_file(Path::new(file), write),
+        (None, ["tokens", file]) => lox.print_tokens(Path::new(file)),
+        (None, ["ast", file]) => lox.print_ast(Path::new(file)),
+        (None, [command, ..]) if COMMANDS.contains(command) => {
+            usage(&format!("wrong number of arguments for `{}`", command))
+        }
+        (None, [file, args @ ..]) => run_script(&mut lox, file, args),
+    };
+
+    match result {
+        Ok(()) => exitcode::OK,
+        Err(failure) => {
+            if !failure.is_reported() {
+                eprintln!("Error: {}", failure);
+            }
+
+            failure.exit_code()
+        }
+    }
+}
+
+// `-` reads the script from stdin
+fn run_script(lox: &mut Lox, file: &str, args: &[&str]) -> Result<(), Failure> {
+    lox.set_args(args);
+
+    match file {
+        "-" => lox.run_stdin(),
+        file => lox.run_file(Path::new(file)),
+    }
+}
+
+fn usage(message: &str) -> ! {
+    eprintln!(
+        "error: {}\n\nRun `yaliir --help` to see the commands and options.",
+        message
+    );
+
+    process::exit(exitcode::USAGE);
+}
+
+fn explain(code: Option<String>) -> ! {
+    match code.as_deref().and_then(codes::explain) {
+        Some(error_code) => {
+            println!(
+                "{}: {}\n\n{}",
+                error_code.code, error_code.title, error_code.explanation
+            );
+
+            process::exit(exitcode::OK);
+        }
+        None => {
+            eprintln!("Unknown error code: {}", code.unwrap_or_default());
 
             process::exit(exitcode::USAGE);
         }
     }
+}
+
+// `all` or a single lint name
+fn lints(name: &str) -> Vec<Lint> {
+    if name == "all" {
+        return Lint::ALL.to_vec();
+    }
+
+    match Lint::from_name(name) {
+        Some(lint) => vec![lint],
+        None => {
+            let names = Lint::ALL.map(Lint::name).join(", ");
+
+            eprintln!("Unknown lint '{}', expected all, {}", name, names);
 
-    Ok(())
+            process::exit(exitcode::USAGE);
+        }
+    }
 }
@@ -1,17 +1,48 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
 use std::fmt;
+use std::rc::Rc;
 
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, Signed, ToPrimitive};
+use rust_decimal::Decimal;
+
+use crate::event_loop::{FutureState, SharedFuture};
 use crate::function::Function;
+use crate::scheduler::SharedChannel;
 
 #[derive(Clone, Debug)]
 pub enum Object {
     Null,
     Boolean(bool),
     Number(f64),
+    Int(i64),
+    BigInt(BigInt),
+    Decimal(Decimal),
     String(String),
+    List(Rc<Vec<Object>>),
     Callable(Function),
+    Channel(SharedChannel),
+    Future(SharedFuture),
 }
 
 impl Object {
+    // for error messages
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::Null => "nil",
+            Object::Boolean(_) => "boolean",
+            Object::Number(_) => "float",
+            Object::Int(_) | Object::BigInt(_) => "integer",
+            Object::Decimal(_) => "decimal",
+            Object::String(_) => "string",
+            Object::List(_) => "list",
+            Object::Callable(_) => "function",
+            Object::Channel(_) => "channel",
+            Object::Future(_) => "future",
+        }
+    }
+
     pub fn is_truthy(&self) -> bool {
         match *self {
             Object::Null => false,
@@ -19,6 +50,101 @@ impl Object {
             _ => true,
         }
     }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            Object::Number(_) | Object::Int(_) | Object::BigInt(_) | Object::Decimal(_)
+        )
+    }
+
+    // big integers that fit into i64 always become plain integers again
+    pub fn from_big_int(value: BigInt) -> Self {
+        match i64::try_from(&value) {
+            Ok(value) => Object::Int(value),
+            Err(_) => Object::BigInt(value),
+        }
+    }
+
+    pub fn to_float(&self) -> Option<f64> {
+        match self {
+            Object::Number(value) => Some(*value),
+            Object::Int(value) => Some(*value as f64),
+            Object::BigInt(value) => value.to_f64(),
+            Object::Decimal(value) => value.to_f64(),
+            _ => None,
+        }
+    }
+
+    pub fn to_big_int(&self) -> Option<BigInt> {
+        match self {
+            Object::Int(value) => Some(BigInt::from(*value)),
+            Object::BigInt(value) => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn to_decimal(&self) -> Option<Decimal> {
+        match self {
+            Object::Int(value) => Some(Decimal::from(*value)),
+            Object::BigInt(value) => value
+                .to_i128()
+                .and_then(|v| Decimal::try_from_i128_with_scale(v, 0).ok()),
+            Object::Decimal(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    // Exact: integers are compared as integers, decimals as decimals and integers with floats
+    // without rounding either. Decimals and floats don't mix, they are unordered like in arithmetic.
+    pub fn numeric_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Object::Int(value1), Object::Int(value2)) => Some(value1.cmp(value2)),
+            (Object::Number(value1), Object::Number(value2)) => value1.partial_cmp(value2),
+            (Object::Decimal(_), Object::Number(_)) | (Object::Number(_), Object::Decimal(_)) => {
+                None
+            }
+            (Object::Number(value), _) => {
+                Some(int_float_cmp(&other.to_big_int()?, *value)?.reverse())
+            }
+            (_, Object::Number(value)) => int_float_cmp(&self.to_big_int()?, *value),
+            (Object::Decimal(_), _) | (_, Object::Decimal(_)) => {
+                match (self.to_decimal(), other.to_decimal()) {
+                    (Some(value1), Some(value2)) => Some(value1.cmp(&value2)),
+                    // an integer too large for a decimal is beyond every decimal
+                    (None, _) => Some(sign_ordering(&self.to_big_int()?)),
+                    (_, None) => Some(sign_ordering(&other.to_big_int()?).reverse()),
+                }
+            }
+            _ => Some(self.to_big_int()?.cmp(&other.to_big_int()?)),
+        }
+    }
+}
+
+// compares with the integer part of `float` exactly, the fraction only breaks ties
+fn int_float_cmp(int: &BigInt, float: f64) -> Option<Ordering> {
+    if float.is_infinite() {
+        return Some(if float > 0.0 {
+            Ordering::Less
+        } else {
+            Ordering::Greater
+        });
+    }
+
+    let floor = float.floor();
+
+    Some(match int.cmp(&BigInt::from_f64(floor)?) {
+        Ordering::Equal if floor < float => Ordering::Less,
+        ordering => ordering,
+    })
+}
+
+fn sign_ordering(int: &BigInt) -> Ordering {
+    if int.is_negative() {
+        Ordering::Less
+    } else {
+        Ordering::Greater
+    }
 }
 
 impl fmt::Display for Object {
@@ -26,15 +152,29 @@ impl fmt::Display for Object {
         match self {
             Object::Null => write!(f, "nil"),
             Object::Boolean(v) => write!(f, "{}", v),
-            Object::Number(v) => {
-                let mut text = v.to_string();
-                if let Some(value) = text.strip_prefix(".0") {
-                    text = value.to_owned();
+            // Debug keeps the fractional part so floats can be told apart from integers: 1.0 vs 1
+            Object::Number(v) => write!(f, "{:?}", v),
+            Object::Int(v) => write!(f, "{}", v),
+            Object::BigInt(v) => write!(f, "{}", v),
+            Object::Decimal(v) => write!(f, "{}", v),
+            Object::String(v) => write!(f, "{}", v),
+            Object::List(v) => {
+                write!(f, "[")?;
+                for (i, element) in v.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element)?;
                 }
-                write!(f, "{}", text)
+                write!(f, "]")
             }
-            Object::String(v) => write!(f, "{}", v),
             Object::Callable(v) => write!(f, "{}", v),
+            Object::Channel(_) => write!(f, "<channel>"),
+            Object::Future(v) => match &v.borrow().state {
+                FutureState::Pending => write!(f, "<future pending>"),
+                FutureState::Resolved(value) => write!(f, "<future {}>", value),
+                FutureState::Rejected(message) => write!(f, "<future failed: {}>", message),
+            },
         }
     }
 }
@@ -44,9 +184,14 @@ impl PartialEq for Object {
         match (self, other) {
             // compare same types
             (Object::Null, Object::Null) => true,
-            (Object::Number(value1), Object::Number(value2)) => value1 == value2,
+            (value1, value2) if value1.is_numeric() && value2.is_numeric() => {
+                value1.numeric_cmp(value2) == Some(Ordering::Equal)
+            }
             (Object::String(value1), Object::String(value2)) => value1 == value2,
             (Object::Boolean(value1), Object::Boolean(value2)) => value1 == value2,
+            (Object::List(value1), Object::List(value2)) => value1 == value2,
+            (Object::Channel(value1), Object::Channel(value2)) => Rc::ptr_eq(value1, value2),
+            (Object::Future(value1), Object::Future(value2)) => Rc::ptr_eq(value1, value2),
 
             // any other type combinations including null case from book
             _ => false,
@@ -1,29 +1,69 @@
+use crate::codes::{self, ErrorCode};
+use crate::diagnostic::{Diagnostic, Diagnostics};
 use crate::expression::Expr;
-use crate::lox::Lox;
 use crate::object::Object;
-use crate::statement::Stmt;
+use crate::scanner::Scanner;
+use crate::span::Label;
+use crate::statement::{Pattern, Stmt};
 use crate::token::{Token, TokenType};
 
 use crate::error::LoxError;
 
+// how deep expressions and statements can nest before the parser gives up instead of overflowing
+// the stack, here and in everything walking the tree after it
+const MAX_NESTING: usize = 100;
+
+// Operator chains like `a + b + c`, calls like `f()()` and `else if` arms are parsed in a loop,
+// only the code walking the tree after the parser recurses into them. That takes much less stack
+// per link than a nesting level, this keeps the walkers within the other half of `STACK_SIZE`.
+const MAX_CHAIN: usize = 2_000;
+
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    // set after an error until the parser resynchronizes, errors in between are most likely
+    // caused by the first one and are not reported
+    panic_mode: bool,
+    depth: usize,
+    // links of the chains the current expression or statement is in
+    chain: usize,
 }
 
 impl Parser {
     pub fn new(tokens: &[Token]) -> Self {
+        let mut tokens = tokens.to_vec();
+
+        // everything below relies on the last token being Eof
+        if tokens.last().map(|token| &token.token_type) != Some(&TokenType::Eof) {
+            let (line, column, offset) = tokens.last().map_or((1, 1, 0), |last| {
+                (last.line, last.column + 1, last.offset + last.length)
+            });
+
+            tokens.push(Token {
+                token_type: TokenType::Eof,
+                lexeme: String::new(),
+                line,
+                column,
+                offset,
+                length: 0,
+                doc: None,
+            });
+        }
+
         Self {
-            tokens: tokens.to_vec(),
+            tokens,
             current: 0,
+            panic_mode: false,
+            depth: 0,
+            chain: 0,
         }
     }
 
-    pub fn parse(&mut self, lox: &mut Lox) -> Vec<Stmt> {
+    pub fn parse(&mut self, diagnostics: &mut Diagnostics) -> Vec<Stmt> {
         let mut statements = vec![];
 
         while !self.is_at_end() {
-            if let Some(declaration) = self.declaration(lox) {
+            if let Some(declaration) = self.declaration(diagnostics) {
                 statements.push(declaration);
             }
         }
@@ -31,81 +71,182 @@ impl Parser {
         statements
     }
 
-    fn expression(&mut self, lox: &mut Lox) -> Result<Expr, LoxError> {
-        self.assignment(lox)
+    fn expression(&mut self, diagnostics: &mut Diagnostics) -> Result<Expr, LoxError> {
+        self.nested(diagnostics, Self::assignment)
     }
 
-    fn declaration(&mut self, lox: &mut Lox) -> Option<Stmt> {
-        let stmt = if self.match_(&[TokenType::Fun]) {
-            self.function("function", lox)
-        } else if self.match_(&[TokenType::Var]) {
-            self.var_declaration(lox)
-        } else {
-            self.statement(lox)
-        };
+    // runs `parse` one nesting level deeper
+    fn nested<T>(
+        &mut self,
+        diagnostics: &mut Diagnostics,
+        parse: impl FnOnce(&mut Self, &mut Diagnostics) -> Result<T, LoxError>,
+    ) -> Result<T, LoxError> {
+        let (depth, chain) = (self.depth, self.chain);
+
+        if depth >= MAX_NESTING {
+            return Err(self.error(
+                &self.peek(),
+                codes::NESTING_TOO_DEEP,
+                "Too much nesting.",
+                diagnostics,
+            ));
+        }
+
+        self.depth += 1;
+        let result = parse(self, diagnostics);
+
+        // also drops the links chains inside of `parse` added
+        (self.depth, self.chain) = (depth, chain);
+
+        result
+    }
+
+    // every operator in a chain like `a + b + c` makes the tree one level deeper too
+    fn link(&mut self, diagnostics: &mut Diagnostics) -> Result<(), LoxError> {
+        if self.chain >= MAX_CHAIN {
+            return Err(self.error(
+                &self.peek(),
+                codes::NESTING_TOO_DEEP,
+                "Too long a chain of operators, calls or 'else if'.",
+                diagnostics,
+            ));
+        }
+
+        self.chain += 1;
+
+        Ok(())
+    }
+
+    fn declaration(&mut self, diagnostics: &mut Diagnostics) -> Option<Stmt> {
+        let start = self.current;
+
+        let stmt = self.nested(diagnostics, |parser, diagnostics| {
+            if parser.match_(&[TokenType::Fun]) {
+                parser.function("function", diagnostics)
+            } else if parser.match_(&[TokenType::Var]) {
+                parser.var_declaration(diagnostics)
+            } else {
+                parser.statement(diagnostics)
+            }
+        });
 
         match stmt {
             Err(_) => {
                 self.synchronize();
 
+                // a stray token no statement can start with, like `}` outside of a block
+                if self.current == start {
+                    self.advance();
+                }
+
                 None
             }
-            Ok(stmt) => Some(stmt),
+            Ok(stmt) => {
+                self.panic_mode = false;
+
+                Some(stmt)
+            }
         }
     }
 
-    fn statement(&mut self, lox: &mut Lox) -> Result<Stmt, LoxError> {
-        if self.match_(&[TokenType::For]) {
-            self.for_statement(lox)
+    fn statement(&mut self, diagnostics: &mut Diagnostics) -> Result<Stmt, LoxError> {
+        self.nested(diagnostics, Self::statement_kind)
+    }
+
+    fn statement_kind(&mut self, diagnostics: &mut Diagnostics) -> Result<Stmt, LoxError> {
+        if self.match_(&[TokenType::Assert]) {
+            self.assert_statement(diagnostics)
+        } else if self.match_(&[TokenType::For]) {
+            self.for_statement(diagnostics)
         } else if self.match_(&[TokenType::If]) {
-            self.if_statement(lox)
+            self.if_statement(diagnostics)
         } else if self.match_(&[TokenType::Print]) {
-            self.print_statement(lox)
+            self.print_statement(diagnostics)
         } else if self.match_(&[TokenType::Return]) {
-            self.return_statement(lox)
+            self.return_statement(diagnostics)
+        } else if self.match_(&[TokenType::Spawn]) {
+            self.spawn_statement(diagnostics)
         } else if self.match_(&[TokenType::While]) {
-            self.while_statement(lox)
+            self.while_statement(diagnostics)
         } else if self.match_(&[TokenType::LeftBrace]) {
             Ok(Stmt::Block {
-                statements: self.block(lox)?,
+                statements: self.block(diagnostics)?,
             })
         } else {
-            self.expression_statement(lox)
+            self.expression_statement(diagnostics)
         }
     }
 
-    fn for_statement(&mut self, lox: &mut Lox) -> Result<Stmt, LoxError> {
-        self.consume(&TokenType::LeftParen, "Expect '(' after 'for'.", lox)?;
+    fn assert_statement(&mut self, diagnostics: &mut Diagnostics) -> Result<Stmt, LoxError> {
+        let keyword = self.previous();
+
+        let start = self.current;
+        let condition = self.expression(diagnostics)?;
+        let source = self.source_text(start, self.current);
+
+        let message = if self.match_(&[TokenType::Comma]) {
+            Some(self.expression(diagnostics)?)
+        } else {
+            None
+        };
+
+        self.consume(
+            &TokenType::Semicolon,
+            "Expect ';' after assertion.",
+            diagnostics,
+        )?;
+
+        Ok(Stmt::Assert {
+            keyword,
+            condition,
+            message,
+            source,
+        })
+    }
+
+    fn for_statement(&mut self, diagnostics: &mut Diagnostics) -> Result<Stmt, LoxError> {
+        let keyword = self.previous();
+
+        let paren = self.consume(
+            &TokenType::LeftParen,
+            "Expect '(' after 'for'.",
+            diagnostics,
+        )?;
 
         let initializer = if self.match_(&[TokenType::Semicolon]) {
             None
         } else if self.match_(&[TokenType::Var]) {
-            Some(self.var_declaration(lox)?)
+            Some(self.var_declaration(diagnostics)?)
         } else {
-            Some(self.expression_statement(lox)?)
+            Some(self.expression_statement(diagnostics)?)
         };
 
         let condition = if self.check(&TokenType::Semicolon) {
             None
         } else {
-            Some(self.expression(lox)?)
+            Some(self.expression(diagnostics)?)
         };
 
         self.consume(
             &TokenType::Semicolon,
             "Expect ';' after loop condition.",
-            lox,
+            diagnostics,
         )?;
 
         let increment = if self.check(&TokenType::RightParen) {
             None
         } else {
-            Some(self.expression(lox)?)
+            Some(self.expression(diagnostics)?)
         };
 
-        self.consume(&TokenType::RightParen, "Expect ')' after for clauses.", lox)?;
+        self.consume_closing(
+            &TokenType::RightParen,
+            &paren,
+            "Expect ')' after for clauses.",
+            diagnostics,
+        )?;
 
-        let mut body = self.statement(lox)?;
+        let mut body = self.statement(diagnostics)?;
 
         if let Some(increment) = increment {
             body = Stmt::Block {
@@ -118,6 +259,7 @@ impl Parser {
                 Some(condition) => condition,
                 None => Expr::Literal {
                     object: Object::Boolean(true),
+                    span: keyword.span(),
                 },
             },
             body: Box::new(body),
@@ -132,18 +274,31 @@ impl Parser {
         Ok(body)
     }
 
-    fn if_statement(&mut self, lox: &mut Lox) -> Result<Stmt, LoxError> {
-        self.consume(&TokenType::LeftParen, "Expect '(' after 'if'.", lox)?;
+    fn if_statement(&mut self, diagnostics: &mut Diagnostics) -> Result<Stmt, LoxError> {
+        let paren = self.consume(&TokenType::LeftParen, "Expect '(' after 'if'.", diagnostics)?;
 
-        let condition = self.expression(lox)?;
+        let condition = self.expression(diagnostics)?;
 
-        self.consume(&TokenType::RightParen, "Expect ')' after 'if'.", lox)?;
+        self.consume_closing(
+            &TokenType::RightParen,
+            &paren,
+            "Expect ')' after if condition.",
+            diagnostics,
+        )?;
 
-        let then_branch = self.statement(lox)?;
+        let then_branch = self.statement(diagnostics)?;
         let mut else_branch = None;
 
         if self.match_(&[TokenType::Else]) {
-            else_branch = Some(Box::new(self.statement(lox)?));
+            let branch = if self.check(&TokenType::If) {
+                // `else if` continues the chain instead of nesting
+                self.link(diagnostics)?;
+                self.statement_kind(diagnostics)?
+            } else {
+                self.statement(diagnostics)?
+            };
+
+            else_branch = Some(Box::new(branch));
         }
 
         Ok(Stmt::If {
@@ -153,33 +308,77 @@ impl Parser {
         })
     }
 
-    fn print_statement(&mut self, lox: &mut Lox) -> Result<Stmt, LoxError> {
-        let value = self.expression(lox)?;
+    fn print_statement(&mut self, diagnostics: &mut Diagnostics) -> Result<Stmt, LoxError> {
+        let value = self.expression(diagnostics)?;
 
-        self.consume(&TokenType::Semicolon, "Expect ';' after value.", lox)?;
+        self.consume(
+            &TokenType::Semicolon,
+            "Expect ';' after value.",
+            diagnostics,
+        )?;
 
         Ok(Stmt::Print { value })
     }
 
-    fn return_statement(&mut self, lox: &mut Lox) -> Result<Stmt, LoxError> {
+    fn return_statement(&mut self, diagnostics: &mut Diagnostics) -> Result<Stmt, LoxError> {
         let keyword = self.previous();
 
         let value = if self.check(&TokenType::Semicolon) {
             None
         } else {
-            Some(self.expression(lox)?)
+            Some(self.expression(diagnostics)?)
         };
 
-        self.consume(&TokenType::Semicolon, "Expect ';' after return value.", lox)?;
+        self.consume(
+            &TokenType::Semicolon,
+            "Expect ';' after return value.",
+            diagnostics,
+        )?;
 
         Ok(Stmt::Return { keyword, value })
     }
 
-    fn var_declaration(&mut self, lox: &mut Lox) -> Result<Stmt, LoxError> {
-        let name = self.consume(&TokenType::Identifier, "Expect variable name.", lox)?;
+    fn spawn_statement(&mut self, diagnostics: &mut Diagnostics) -> Result<Stmt, LoxError> {
+        let keyword = self.previous();
+
+        let call = self.call(diagnostics)?;
+
+        self.consume(
+            &TokenType::Semicolon,
+            "Expect ';' after spawned call.",
+            diagnostics,
+        )?;
+
+        match call {
+            Expr::Call {
+                callee,
+                paren,
+                arguments,
+            } => Ok(Stmt::Spawn {
+                keyword,
+                callee: *callee,
+                paren,
+                arguments,
+            }),
+            _ => Err(self.error(
+                &keyword,
+                codes::SPAWN_WITHOUT_CALL,
+                "Expect function call after 'spawn'.",
+                diagnostics,
+            )),
+        }
+    }
+
+    fn var_declaration(&mut self, diagnostics: &mut Diagnostics) -> Result<Stmt, LoxError> {
+        if self.check(&TokenType::LeftBracket) || self.check(&TokenType::LeftBrace) {
+            return self.destructure_declaration(diagnostics);
+        }
+
+        let name = self.consume(&TokenType::Identifier, "Expect variable name.", diagnostics)?;
+        let type_annotation = self.type_annotation(diagnostics)?;
 
         let initializer = if self.match_(&[TokenType::Equal]) {
-            Some(self.expression(lox)?)
+            Some(self.expression(diagnostics)?)
         } else {
             None
         };
@@ -187,20 +386,115 @@ impl Parser {
         self.consume(
             &TokenType::Semicolon,
             "Expect ';' after variable declaration.",
-            lox,
+            diagnostics,
         )?;
 
-        Ok(Stmt::Var { name, initializer })
+        Ok(Stmt::Var {
+            name,
+            type_annotation,
+            initializer,
+        })
+    }
+
+    fn destructure_declaration(&mut self, diagnostics: &mut Diagnostics) -> Result<Stmt, LoxError> {
+        let pattern = self.pattern(diagnostics)?;
+
+        self.consume(
+            &TokenType::Equal,
+            "Expect '=' after destructuring pattern.",
+            diagnostics,
+        )?;
+
+        let initializer = self.expression(diagnostics)?;
+
+        self.consume(
+            &TokenType::Semicolon,
+            "Expect ';' after variable declaration.",
+            diagnostics,
+        )?;
+
+        Ok(Stmt::Destructure {
+            pattern,
+            initializer,
+        })
+    }
+
+    fn pattern(&mut self, diagnostics: &mut Diagnostics) -> Result<Pattern, LoxError> {
+        if self.match_(&[TokenType::LeftBracket]) {
+            let bracket = self.previous();
+            let mut elements = vec![];
+
+            if !self.check(&TokenType::RightBracket) {
+                loop {
+                    elements.push(self.nested(diagnostics, Self::pattern)?);
+
+                    if !self.match_(&[TokenType::Comma]) {
+                        break;
+                    }
+                }
+            }
+
+            self.consume_closing(
+                &TokenType::RightBracket,
+                &bracket,
+                "Expect ']' after list pattern.",
+                diagnostics,
+            )?;
+
+            Ok(Pattern::List { bracket, elements })
+        } else if self.match_(&[TokenType::LeftBrace]) {
+            let brace = self.previous();
+
+            if !self.check(&TokenType::RightBrace) {
+                loop {
+                    self.consume(&TokenType::Identifier, "Expect field name.", diagnostics)?;
+
+                    if !self.match_(&[TokenType::Comma]) {
+                        break;
+                    }
+                }
+            }
+
+            self.consume_closing(
+                &TokenType::RightBrace,
+                &brace,
+                "Expect '}' after field pattern.",
+                diagnostics,
+            )?;
+
+            // parsed in full so the pattern is reported once, only instances have fields
+            Err(self.error(
+                &brace,
+                codes::UNSUPPORTED,
+                "Field patterns need classes, which are not supported yet.",
+                diagnostics,
+            ))
+        } else {
+            Ok(Pattern::Name(self.consume(
+                &TokenType::Identifier,
+                "Expect variable name.",
+                diagnostics,
+            )?))
+        }
     }
 
-    fn while_statement(&mut self, lox: &mut Lox) -> Result<Stmt, LoxError> {
-        self.consume(&TokenType::LeftParen, "Expect '(' after 'while'.", lox)?;
+    fn while_statement(&mut self, diagnostics: &mut Diagnostics) -> Result<Stmt, LoxError> {
+        let paren = self.consume(
+            &TokenType::LeftParen,
+            "Expect '(' after 'while'.",
+            diagnostics,
+        )?;
 
-        let condition = self.expression(lox)?;
+        let condition = self.expression(diagnostics)?;
 
-        self.consume(&TokenType::RightParen, "Expect ')' after condition.", lox)?;
+        self.consume_closing(
+            &TokenType::RightParen,
+            &paren,
+            "Expect ')' after while condition.",
+            diagnostics,
+        )?;
 
-        let body = self.statement(lox)?;
+        let body = self.statement(diagnostics)?;
 
         Ok(Stmt::While {
             condition,
@@ -208,36 +502,95 @@ impl Parser {
         })
     }
 
-    fn expression_statement(&mut self, lox: &mut Lox) -> Result<Stmt, LoxError> {
-        let value = self.expression(lox)?;
+    fn expression_statement(&mut self, diagnostics: &mut Diagnostics) -> Result<Stmt, LoxError> {
+        let value = self.expression(diagnostics)?;
 
-        self.consume(&TokenType::Semicolon, "Expect ';' after value.", lox)?;
+        if let Expr::Variable { name } = &value {
+            if self.check(&TokenType::Comma) {
+                return self.multi_assignment(name.clone(), diagnostics);
+            }
+        }
+
+        self.consume(
+            &TokenType::Semicolon,
+            "Expect ';' after expression.",
+            diagnostics,
+        )?;
 
         Ok(Stmt::Expression { value })
     }
 
-    fn function(&mut self, kind: &str, lox: &mut Lox) -> Result<Stmt, LoxError> {
+    fn multi_assignment(
+        &mut self,
+        first: Token,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<Stmt, LoxError> {
+        let mut targets = vec![first];
+
+        while self.match_(&[TokenType::Comma]) {
+            targets.push(self.consume(
+                &TokenType::Identifier,
+                "Expect variable name after ','.",
+                diagnostics,
+            )?);
+        }
+
+        self.consume(
+            &TokenType::Equal,
+            "Expect '=' after assignment targets.",
+            diagnostics,
+        )?;
+
+        let mut values = vec![self.expression(diagnostics)?];
+
+        while self.match_(&[TokenType::Comma]) {
+            values.push(self.expression(diagnostics)?);
+        }
+
+        self.consume(
+            &TokenType::Semicolon,
+            "Expect ';' after assigned values.",
+            diagnostics,
+        )?;
+
+        Ok(Stmt::MultiAssign { targets, values })
+    }
+
+    fn function(&mut self, kind: &str, diagnostics: &mut Diagnostics) -> Result<Stmt, LoxError> {
+        let doc = self.previous().doc;
+
         let name = self.consume(
             &TokenType::Identifier,
             &format!("Expect {} name.", kind),
-            lox,
+            diagnostics,
         )?;
 
-        self.consume(
+        let paren = self.consume(
             &TokenType::LeftParen,
             &format!("Expect '(' after {} name.", kind),
-            lox,
+            diagnostics,
         )?;
 
         let mut params = vec![];
+        let mut param_types = vec![];
 
         if !self.check(&TokenType::RightParen) {
             loop {
                 if params.len() > 255 {
-                    self.error(&self.peek(), "Can't have more than 255 parameters.", lox);
+                    self.error(
+                        &self.peek(),
+                        codes::TOO_MANY_ARGUMENTS,
+                        "Can't have more than 255 parameters.",
+                        diagnostics,
+                    );
                 }
 
-                params.push(self.consume(&TokenType::Identifier, "Expect parameter name.", lox)?);
+                params.push(self.consume(
+                    &TokenType::Identifier,
+                    "Expect parameter name.",
+                    diagnostics,
+                )?);
+                param_types.push(self.type_annotation(diagnostics)?);
 
                 if !self.match_(&[TokenType::Comma]) {
                     break;
@@ -245,38 +598,75 @@ impl Parser {
             }
         }
 
-        self.consume(&TokenType::RightParen, "Expect ')' after parameters.", lox)?;
+        self.consume_closing(
+            &TokenType::RightParen,
+            &paren,
+            "Expect ')' after parameters.",
+            diagnostics,
+        )?;
+
+        let return_type = self.type_annotation(diagnostics)?;
+
         self.consume(
             &TokenType::LeftBrace,
-            &format!("Expect '{{' after {} body.", kind),
-            lox,
+            &format!("Expect '{{' before {} body.", kind),
+            diagnostics,
         )?;
 
-        let body = self.block(lox)?;
+        let body = self.block(diagnostics)?;
 
-        Ok(Stmt::Function { name, params, body })
+        Ok(Stmt::Function {
+            name,
+            params,
+            param_types,
+            return_type,
+            body,
+            doc,
+        })
+    }
+
+    fn type_annotation(
+        &mut self,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<Option<Token>, LoxError> {
+        if self.match_(&[TokenType::Colon]) {
+            Ok(Some(self.consume(
+                &TokenType::Identifier,
+                "Expect type name after ':'.",
+                diagnostics,
+            )?))
+        } else {
+            Ok(None)
+        }
     }
 
-    fn block(&mut self, lox: &mut Lox) -> Result<Vec<Stmt>, LoxError> {
+    // called after the opening brace
+    fn block(&mut self, diagnostics: &mut Diagnostics) -> Result<Vec<Stmt>, LoxError> {
+        let brace = self.previous();
         let mut statements = vec![];
 
         while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
-            if let Some(declaration) = self.declaration(lox) {
+            if let Some(declaration) = self.declaration(diagnostics) {
                 statements.push(declaration);
             }
         }
 
-        self.consume(&TokenType::RightBrace, "Expect '}' after block.", lox)?;
+        self.consume_closing(
+            &TokenType::RightBrace,
+            &brace,
+            "Expect '}' after block.",
+            diagnostics,
+        )?;
 
         Ok(statements)
     }
 
-    fn assignment(&mut self, lox: &mut Lox) -> Result<Expr, LoxError> {
-        let expr = self.or(lox)?;
+    fn assignment(&mut self, diagnostics: &mut Diagnostics) -> Result<Expr, LoxError> {
+        let expr = self.or(diagnostics)?;
 
         if self.match_(&[TokenType::Equal]) {
             let equals = self.previous();
-            let value = self.assignment(lox)?;
+            let value = self.nested(diagnostics, Self::assignment)?;
 
             match expr {
                 Expr::Variable { name } => {
@@ -285,19 +675,25 @@ impl Parser {
                         value: Box::new(value),
                     });
                 }
-                _ => self.error(&equals, "Invalid assignment target.", lox),
+                _ => self.error(
+                    &equals,
+                    codes::INVALID_ASSIGNMENT_TARGET,
+                    "Invalid assignment target.",
+                    diagnostics,
+                ),
             };
         }
 
         Ok(expr)
     }
 
-    fn or(&mut self, lox: &mut Lox) -> Result<Expr, LoxError> {
-        let mut expr = self.and(lox)?;
+    fn or(&mut self, diagnostics: &mut Diagnostics) -> Result<Expr, LoxError> {
+        let mut expr = self.and(diagnostics)?;
 
         while self.match_(&[TokenType::Or]) {
             let operator = self.previous();
-            let right = self.and(lox)?;
+            self.link(diagnostics)?;
+            let right = self.and(diagnostics)?;
 
             expr = Expr::Logical {
                 left: Box::new(expr),
@@ -309,12 +705,13 @@ impl Parser {
         Ok(expr)
     }
 
-    fn and(&mut self, lox: &mut Lox) -> Result<Expr, LoxError> {
-        let mut expr = self.equality(lox)?;
+    fn and(&mut self, diagnostics: &mut Diagnostics) -> Result<Expr, LoxError> {
+        let mut expr = self.equality(diagnostics)?;
 
         while self.match_(&[TokenType::And]) {
             let operator = self.previous();
-            let right = self.equality(lox)?;
+            self.link(diagnostics)?;
+            let right = self.equality(diagnostics)?;
 
             expr = Expr::Logical {
                 left: Box::new(expr),
@@ -326,12 +723,13 @@ impl Parser {
         Ok(expr)
     }
 
-    fn equality(&mut self, lox: &mut Lox) -> Result<Expr, LoxError> {
-        let mut expr = self.comparison(lox)?;
+    fn equality(&mut self, diagnostics: &mut Diagnostics) -> Result<Expr, LoxError> {
+        let mut expr = self.comparison(diagnostics)?;
 
         while self.match_(&[TokenType::BangEqual, TokenType::EqualEqual]) {
             let operator = self.previous();
-            let right = self.comparison(lox)?;
+            self.link(diagnostics)?;
+            let right = self.comparison(diagnostics)?;
 
             expr = Expr::Binary {
                 left: Box::new(expr),
@@ -343,8 +741,8 @@ impl Parser {
         Ok(expr)
     }
 
-    fn comparison(&mut self, lox: &mut Lox) -> Result<Expr, LoxError> {
-        let mut expr = self.term(lox)?;
+    fn comparison(&mut self, diagnostics: &mut Diagnostics) -> Result<Expr, LoxError> {
+        let mut expr = self.term(diagnostics)?;
 
         while self.match_(&[
             TokenType::Greater,
@@ -353,7 +751,8 @@ impl Parser {
             TokenType::LessEqual,
         ]) {
             let operator = self.previous();
-            let right = self.term(lox)?;
+            self.link(diagnostics)?;
+            let right = self.term(diagnostics)?;
 
             expr = Expr::Binary {
                 left: Box::new(expr),
@@ -365,12 +764,13 @@ impl Parser {
         Ok(expr)
     }
 
-    fn term(&mut self, lox: &mut Lox) -> Result<Expr, LoxError> {
-        let mut expr = self.factor(lox)?;
+    fn term(&mut self, diagnostics: &mut Diagnostics) -> Result<Expr, LoxError> {
+        let mut expr = self.factor(diagnostics)?;
 
         while self.match_(&[TokenType::Minus, TokenType::Plus]) {
             let operator = self.previous();
-            let right = self.factor(lox)?;
+            self.link(diagnostics)?;
+            let right = self.factor(diagnostics)?;
 
             expr = Expr::Binary {
                 left: Box::new(expr),
@@ -382,12 +782,13 @@ impl Parser {
         Ok(expr)
     }
 
-    fn factor(&mut self, lox: &mut Lox) -> Result<Expr, LoxError> {
-        let mut expr = self.unary(lox)?;
+    fn factor(&mut self, diagnostics: &mut Diagnostics) -> Result<Expr, LoxError> {
+        let mut expr = self.unary(diagnostics)?;
 
         while self.match_(&[TokenType::Slash, TokenType::Star]) {
             let operator = self.previous();
-            let right = self.unary(lox)?;
+            self.link(diagnostics)?;
+            let right = self.unary(diagnostics)?;
 
             expr = Expr::Binary {
                 left: Box::new(expr),
@@ -399,38 +800,55 @@ impl Parser {
         Ok(expr)
     }
 
-    fn unary(&mut self, lox: &mut Lox) -> Result<Expr, LoxError> {
+    fn unary(&mut self, diagnostics: &mut Diagnostics) -> Result<Expr, LoxError> {
         if self.match_(&[TokenType::Bang, TokenType::Minus]) {
             let operator = self.previous();
-            let right = self.unary(lox)?;
+            let right = self.nested(diagnostics, Self::unary)?;
 
             Ok(Expr::Unary {
                 operator,
                 right: Box::new(right),
             })
         } else {
-            self.call(lox)
+            self.call(diagnostics)
         }
     }
 
-    fn finish_call(&mut self, callee: Expr, lox: &mut Lox) -> Result<Expr, LoxError> {
+    fn finish_call(
+        &mut self,
+        callee: Expr,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<Expr, LoxError> {
+        let open = self.previous();
         let mut arguments = vec![];
 
-        if !self.check(&TokenType::RightParen) {
-            loop {
+        let elements = self.elements(
+            &TokenType::RightParen,
+            diagnostics,
+            |parser, diagnostics| {
                 if arguments.len() >= 255 {
-                    self.error(&self.peek(), "Can't have more than 255 arguments.", lox);
+                    parser.error(
+                        &parser.peek(),
+                        codes::TOO_MANY_ARGUMENTS,
+                        "Can't have more than 255 arguments.",
+                        diagnostics,
+                    );
                 }
 
-                arguments.push(self.expression(lox)?);
+                arguments.push(parser.expression(diagnostics)?);
 
-                if !self.match_(&[TokenType::Comma]) {
-                    break;
-                }
-            }
-        }
+                Ok(())
+            },
+        );
 
-        let paren = self.consume(&TokenType::RightParen, "Expect ')' after arguments.", lox)?;
+        let paren = self.consume_closing(
+            &TokenType::RightParen,
+            &open,
+            "Expect ')' after arguments.",
+            diagnostics,
+        )?;
+
+        elements?;
 
         Ok(Expr::Call {
             callee: Box::new(callee),
@@ -439,12 +857,14 @@ impl Parser {
         })
     }
 
-    fn call(&mut self, lox: &mut Lox) -> Result<Expr, LoxError> {
-        let mut expr = self.primary(lox)?;
+    fn call(&mut self, diagnostics: &mut Diagnostics) -> Result<Expr, LoxError> {
+        let mut expr = self.primary(diagnostics)?;
 
         loop {
             if self.match_(&[TokenType::LeftParen]) {
-                expr = self.finish_call(expr, lox)?;
+                self.link(diagnostics)?;
+
+                expr = self.finish_call(expr, diagnostics)?;
             } else {
                 break;
             }
@@ -453,41 +873,99 @@ impl Parser {
         Ok(expr)
     }
 
-    fn primary(&mut self, lox: &mut Lox) -> Result<Expr, LoxError> {
+    fn primary(&mut self, diagnostics: &mut Diagnostics) -> Result<Expr, LoxError> {
         // much cleaner than using self.match_
         // the downside is having to call self.advance manually
         let current = self.peek();
+        let span = current.span();
 
         let expr = match current.token_type {
             TokenType::False => Expr::Literal {
                 object: Object::Boolean(false),
+                span,
             },
             TokenType::True => Expr::Literal {
                 object: Object::Boolean(true),
+                span,
             },
             TokenType::Nil => Expr::Literal {
                 object: Object::Null,
+                span,
             },
             TokenType::Number { literal } => Expr::Literal {
                 object: Object::Number(literal),
+                span,
+            },
+            TokenType::Integer { literal } => Expr::Literal {
+                object: Object::Int(literal),
+                span,
+            },
+            TokenType::BigInteger { literal } => Expr::Literal {
+                object: Object::BigInt(literal),
+                span,
+            },
+            TokenType::Decimal { literal } => Expr::Literal {
+                object: Object::Decimal(literal),
+                span,
             },
             TokenType::String { literal } => Expr::Literal {
                 object: Object::String(literal),
+                span,
             },
             TokenType::Identifier => Expr::Variable { name: current },
             TokenType::LeftParen => {
-                // NOTE: this causes infinite recursion!!!
-                // probably fixed in next chapters
-                let expr = self.expression(lox)?;
+                self.advance();
 
-                self.consume(&TokenType::RightParen, "Expect ')' after expression.", lox)?;
+                let expr = self.expression(diagnostics)?;
 
-                Expr::Grouping {
+                let paren = self.consume_closing(
+                    &TokenType::RightParen,
+                    &current,
+                    "Expect ')' after expression.",
+                    diagnostics,
+                )?;
+
+                return Ok(Expr::Grouping {
                     expression: Box::new(expr),
-                }
+                    span: current.span().to(paren.span()),
+                });
+            }
+            TokenType::LeftBracket => {
+                self.advance();
+
+                let mut elements = vec![];
+
+                let parsed = self.elements(
+                    &TokenType::RightBracket,
+                    diagnostics,
+                    |parser, diagnostics| {
+                        elements.push(parser.expression(diagnostics)?);
+
+                        Ok(())
+                    },
+                );
+
+                self.consume_closing(
+                    &TokenType::RightBracket,
+                    &current,
+                    "Expect ']' after list elements.",
+                    diagnostics,
+                )?;
+
+                parsed?;
+
+                return Ok(Expr::List {
+                    bracket: current,
+                    elements,
+                });
             }
             _ => {
-                return Err(self.error(&current, "Expect expression.", lox));
+                return Err(self.error(
+                    &current,
+                    codes::EXPECTED_EXPRESSION,
+                    "Expect expression.",
+                    diagnostics,
+                ));
             }
         };
 
@@ -496,6 +974,23 @@ impl Parser {
         Ok(expr)
     }
 
+    // text of tokens in range with any whitespace between them collapsed into a single space
+    fn source_text(&self, start: usize, end: usize) -> String {
+        let mut text = String::new();
+        let mut previous_end = None;
+
+        for token in &self.tokens[start..end] {
+            if matches!(previous_end, Some(previous_end) if token.offset > previous_end) {
+                text.push(' ');
+            }
+
+            text.push_str(&token.lexeme);
+            previous_end = Some(token.offset + token.lexeme.len());
+        }
+
+        text
+    }
+
     fn match_(&mut self, types: &[TokenType]) -> bool {
         for token_type in types {
             if self.check(token_type) {
@@ -511,40 +1006,165 @@ impl Parser {
         &mut self,
         token_type: &TokenType,
         message: &str,
-        lox: &mut Lox,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<Token, LoxError> {
+        if !self.check(token_type) {
+            return Err(self.error(&self.peek(), codes::EXPECTED_TOKEN, message, diagnostics));
+        }
+
+        Ok(self.advance())
+    }
+
+    // like consume, but also points at the unclosed `open` token
+    fn consume_closing(
+        &mut self,
+        token_type: &TokenType,
+        open: &Token,
+        message: &str,
+        diagnostics: &mut Diagnostics,
     ) -> Result<Token, LoxError> {
         if !self.check(token_type) {
-            return Err(self.error(&self.peek(), message, lox));
+            let diagnostic = Diagnostic {
+                labels: vec![Label::new(
+                    open.span(),
+                    format!("to match this '{}'", open.lexeme),
+                )],
+                ..Diagnostic::error(Some(self.peek().span()), message)
+                    .with_code(codes::EXPECTED_TOKEN)
+            };
+
+            return Err(self.report(diagnostic, diagnostics));
         }
 
         Ok(self.advance())
     }
 
-    fn error(&mut self, token: &Token, message: &str, lox: &mut Lox) -> LoxError {
-        lox.parser_error(token, message);
+    // Parses comma separated elements with `element` until `close`, which is left for the caller
+    // to consume. A broken element does not end the list: the rest of it up to the next comma
+    // is skipped and parsing continues, the first error is returned once all elements are done.
+    fn elements(
+        &mut self,
+        close: &TokenType,
+        diagnostics: &mut Diagnostics,
+        mut element: impl FnMut(&mut Self, &mut Diagnostics) -> Result<(), LoxError>,
+    ) -> Result<(), LoxError> {
+        let mut result = Ok(());
+
+        if self.check(close) {
+            return result;
+        }
 
-        LoxError::Parser
+        loop {
+            if let Err(err) = element(self, diagnostics) {
+                if !self.skip_element(close) {
+                    return Err(err);
+                }
+
+                result = Err(err);
+            }
+
+            if !self.match_(&[TokenType::Comma]) {
+                return result;
+            }
+        }
     }
 
-    fn synchronize(&mut self) {
-        self.advance();
+    // skips to the next top level `,` or `close`, returns false if neither is found before the
+    // statement ends
+    fn skip_element(&mut self, close: &TokenType) -> bool {
+        let mut depth = 0;
 
         while !self.is_at_end() {
-            if self.previous().token_type == TokenType::Semicolon {
-                return;
+            match self.peek().token_type {
+                TokenType::LeftParen | TokenType::LeftBracket => depth += 1,
+                TokenType::RightParen | TokenType::RightBracket if depth > 0 => depth -= 1,
+                TokenType::Comma if depth == 0 => break,
+                ref token_type if depth == 0 && token_type == close => break,
+                TokenType::Semicolon | TokenType::LeftBrace | TokenType::RightBrace => {
+                    return false
+                }
+                _ => {}
             }
 
+            self.advance();
+        }
+
+        if self.is_at_end() {
+            return false;
+        }
+
+        // errors after this point are not caused by the broken element
+        self.panic_mode = false;
+
+        true
+    }
+
+    fn error(
+        &mut self,
+        token: &Token,
+        code: &'static ErrorCode,
+        message: &str,
+        diagnostics: &mut Diagnostics,
+    ) -> LoxError {
+        self.report(
+            Diagnostic::error(Some(token.span()), message).with_code(code),
+            diagnostics,
+        )
+    }
+
+    fn report(&mut self, diagnostic: Diagnostic, diagnostics: &mut Diagnostics) -> LoxError {
+        if !self.panic_mode {
+            diagnostics.push(diagnostic);
+        }
+
+        self.panic_mode = true;
+
+        LoxError::Parser
+    }
+
+    // Skips to the start of the next statement. Braced blocks inside of the broken statement are
+    // skipped whole, a `}` closing the enclosing block is left for the block to consume.
+    fn synchronize(&mut self) {
+        self.panic_mode = false;
+
+        let mut depth = 0;
+
+        while !self.is_at_end() {
             match self.peek().token_type {
-                TokenType::Class
+                TokenType::LeftBrace => depth += 1,
+                TokenType::RightBrace if depth == 0 => return,
+                TokenType::RightBrace => {
+                    depth -= 1;
+
+                    if depth == 0 {
+                        self.advance();
+
+                        return;
+                    }
+                }
+                TokenType::Semicolon if depth == 0 => {
+                    self.advance();
+
+                    return;
+                }
+                TokenType::Assert
+                | TokenType::Class
                 | TokenType::Fun
                 | TokenType::Var
                 | TokenType::For
                 | TokenType::If
                 | TokenType::While
                 | TokenType::Print
-                | TokenType::Return => return,
-                _ => self.advance(),
-            };
+                | TokenType::Return
+                | TokenType::Spawn
+                    if depth == 0 =>
+                {
+                    return
+                }
+                _ => {}
+            }
+
+            self.advance();
         }
     }
 
@@ -568,17 +1188,322 @@ impl Parser {
         self.peek().token_type == TokenType::Eof
     }
 
+    // never moves past Eof, which Parser::new makes sure is there
     fn peek(&self) -> Token {
-        self.tokens
-            .get(self.current)
-            .expect("exhausted token array")
-            .clone()
+        self.tokens[self.current.min(self.tokens.len() - 1)].clone()
     }
 
     fn previous(&self) -> Token {
-        self.tokens
-            .get(self.current - 1)
-            .expect("exhausted token array")
-            .clone()
+        self.tokens[self.current.saturating_sub(1)].clone()
+    }
+}
+
+/// Scans and parses `source` on its own, with problems of both going to `diagnostics`
+pub(crate) fn parse_source(source: &str, diagnostics: &mut Diagnostics) -> Vec<Stmt> {
+    let tokens = Scanner::new(source).scan_tokens(diagnostics).clone();
+
+    Parser::new(&tokens).parse(diagnostics)
+}
+
+#[cfg(test)]
+mod test {
+    use std::panic;
+    use std::thread;
+
+    use super::{parse_source, Parser};
+
+    use crate::diagnostic::Diagnostics;
+    use crate::scanner::Scanner;
+
+    // (line, column, message) of every error
+    fn errors(source: &str) -> Vec<(i32, usize, String)> {
+        let mut diagnostics = Diagnostics::default();
+        parse_source(source, &mut diagnostics);
+
+        diagnostics
+            .drain()
+            .map(|diagnostic| {
+                let span = diagnostic.span.expect("syntax errors have a location");
+
+                (span.line, span.column, diagnostic.message)
+            })
+            .collect()
+    }
+
+    fn messages(source: &str) -> Vec<String> {
+        errors(source)
+            .into_iter()
+            .map(|(_, _, message)| message)
+            .collect()
+    }
+
+    #[test]
+    fn if_condition_messages() {
+        assert_eq!(
+            messages("if (a print 1;"),
+            ["Expect ')' after if condition."]
+        );
+        assert_eq!(
+            messages("while (a print 1;"),
+            ["Expect ')' after while condition."]
+        );
+        assert_eq!(
+            messages("fun f() print 1;"),
+            ["Expect '{' before function body."]
+        );
+    }
+
+    #[test]
+    fn field_patterns_are_unsupported() {
+        assert_eq!(
+            errors("var {x, y} = point;\nprint 1"),
+            vec![
+                (
+                    1,
+                    5,
+                    "Field patterns need classes, which are not supported yet.".to_owned()
+                ),
+                (2, 8, "Expect ';' after value.".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_in_blocks_do_not_cascade() {
+        assert_eq!(
+            errors("fun f() {\n  var = 1;\n  print 2\n}\nprint 3;\nprint ;"),
+            [
+                (2, 7, "Expect variable name.".to_owned()),
+                (4, 1, "Expect ';' after value.".to_owned()),
+                (6, 7, "Expect expression.".to_owned()),
+            ]
+        );
+        assert_eq!(
+            messages("if (a { print 1; } print 2;\nprint 3"),
+            ["Expect ')' after if condition.", "Expect ';' after value."]
+        );
+    }
+
+    #[test]
+    fn recovers_at_argument_boundaries() {
+        assert_eq!(
+            errors("f(1 +, g(2 *), 3);\nprint [1, +, 3];"),
+            [
+                (1, 6, "Expect expression.".to_owned()),
+                (1, 13, "Expect expression.".to_owned()),
+                (2, 11, "Expect expression.".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn unclosed_delimiters_point_at_opening() {
+        let mut diagnostics = Diagnostics::default();
+        parse_source("fun f() {\n  print (1;", &mut diagnostics);
+
+        let labels = diagnostics
+            .drain()
+            .map(|diagnostic| {
+                let label = &diagnostic.labels[0];
+
+                (diagnostic.message, label.span.line, label.message.clone())
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            labels,
+            [
+                (
+                    "Expect ')' after expression.".to_owned(),
+                    2,
+                    "to match this '('".to_owned()
+                ),
+                (
+                    "Expect '}' after block.".to_owned(),
+                    1,
+                    "to match this '{'".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        let nesting = "Too much nesting.";
+        let chain = "Too long a chain of operators, calls or 'else if'.";
+
+        let inputs = [
+            ("(".repeat(10_000), nesting),
+            ("{".repeat(10_000), nesting),
+            (format!("print {}1;", "!".repeat(10_000)), nesting),
+            (
+                format!("var {}a{} = 1;", "[".repeat(10_000), "]".repeat(10_000)),
+                nesting,
+            ),
+            (format!("a{} = 1;", " = a".repeat(10_000)), nesting),
+            ("if (a) ".repeat(10_000), nesting),
+            (format!("print 1{};", " + 1".repeat(10_000)), chain),
+            (format!("f{};", "()".repeat(10_000)), chain),
+            (
+                format!("if (a) 1;{}", " else if (a) 1;".repeat(10_000)),
+                chain,
+            ),
+        ];
+
+        // test threads get 2 MiB of stack, much less than the interpreter thread the limit is for
+        let missing = thread::Builder::new()
+            .stack_size(crate::interpreter::STACK_SIZE)
+            .spawn(move || {
+                let deepest = format!("print {}1{};", "(".repeat(90), ")".repeat(90));
+
+                assert!(messages(&deepest).is_empty());
+
+                inputs
+                    .iter()
+                    .filter(|(input, message)| !messages(input).contains(&message.to_string()))
+                    .map(|(_, message)| *message)
+                    .collect::<Vec<_>>()
+            })
+            .expect("could not spawn test thread")
+            .join()
+            .expect("test thread panicked");
+
+        assert!(missing.is_empty(), "missing errors: {:?}", missing);
+    }
+
+    #[test]
+    fn long_flat_chains_are_fine() {
+        let inputs = [
+            format!("print 1{};", " + 1".repeat(500)),
+            format!("print true{};", " and true".repeat(500)),
+            format!("f{};", "()".repeat(500)),
+            format!("if (a) 1;{} else 2;", " else if (a) 1;".repeat(500)),
+            // chains inside of groupings don't add up to nesting either
+            format!("print {}1{};", "(1 + ".repeat(50), " + 1)".repeat(50)),
+        ];
+
+        let errors = thread::Builder::new()
+            .stack_size(crate::interpreter::STACK_SIZE)
+            .spawn(move || {
+                inputs
+                    .iter()
+                    .flat_map(|input| messages(input))
+                    .collect::<Vec<_>>()
+            })
+            .expect("could not spawn test thread")
+            .join()
+            .expect("test thread panicked");
+
+        assert!(errors.is_empty(), "{:?}", errors);
+    }
+
+    #[test]
+    fn missing_eof_is_added() {
+        let mut diagnostics = Diagnostics::default();
+
+        assert!(Parser::new(&[]).parse(&mut diagnostics).is_empty());
+
+        let tokens = Scanner::new("print 1;")
+            .scan_tokens(&mut diagnostics)
+            .clone();
+
+        assert_eq!(
+            Parser::new(&tokens[..tokens.len() - 1])
+                .parse(&mut diagnostics)
+                .len(),
+            1
+        );
+        assert!(!diagnostics.has_errors());
+    }
+
+    #[test]
+    fn malformed_inputs_report_errors() {
+        let inputs = [
+            "(",
+            ")",
+            "}",
+            "{",
+            "]",
+            "[",
+            ";",
+            ",",
+            "=",
+            "var",
+            "var;",
+            "var 1 = 2;",
+            "var [a, = b;",
+            "var {a b} = c;",
+            "var a: = 1;",
+            "fun",
+            "fun (",
+            "fun f(",
+            "fun f(a,) {}",
+            "fun f(a b) {}",
+            "fun f() {",
+            "fun f(): {}",
+            "if",
+            "if (",
+            "if () print 1;",
+            "if (a) else print 1;",
+            "while (",
+            "for",
+            "for (;;",
+            "for (var i = 0; i < 1; i = i + 1",
+            "for (var i = 0 i < 1) {}",
+            "print",
+            "print 1 +;",
+            "print (1, 2);",
+            "print [1, 2;",
+            "print f(1, 2;",
+            "print f(,);",
+            "return",
+            "assert",
+            "assert 1,;",
+            "spawn;",
+            "spawn f;",
+            "spawn f(;",
+            "1 = 2;",
+            "a, 1 = 2, 3;",
+            "a, b = 1,;",
+            "a, b 1;",
+            "!;",
+            "- -;",
+            "{ { { print 1; }",
+            "} } }",
+            "((((((((((",
+            "]]]]]]]]]]",
+            "f(((((((((",
+            "print 1 print 2 print 3",
+            "class A {}",
+            "this.x = 1",
+            "super.f();",
+        ];
+
+        for input in inputs {
+            let result = panic::catch_unwind(|| errors(input));
+
+            match result {
+                Ok(errors) => assert!(!errors.is_empty(), "no errors for {:?}", input),
+                Err(_) => panic!("parser panicked on {:?}", input),
+            }
+        }
+    }
+
+    #[test]
+    fn valid_inputs_report_nothing() {
+        let inputs = [
+            "",
+            "print 1;",
+            "var [a, [b, c]] = [1, [2, 3]];",
+            "fun f(a: Int, b): Int { return a; } f(1, 2);",
+            "for (var i = 0; i < 3; i = i + 1) { if (i) print i; else {} }",
+            "a, b = b, a;",
+            "spawn f(1);",
+            "assert 1 == 1, \"math\";",
+        ];
+
+        for input in inputs {
+            assert!(errors(input).is_empty(), "errors for {:?}", input);
+        }
     }
 }
@@ -0,0 +1,406 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+use unicode_xid::UnicodeXID;
+
+use crate::codes;
+use crate::diagnostic::Diagnostics;
+use crate::expression::Expr;
+use crate::object::Object;
+use crate::parser::{parse_source, Parser};
+use crate::scanner::{Scanner, KEYWORDS};
+use crate::statement::Stmt;
+use crate::token::TokenType;
+
+/// Whether `source` can be run as it is or the REPL should keep reading lines. Input that is
+/// wrong in a way more lines can't fix counts as complete, so its errors get reported.
+pub fn is_complete(source: &str) -> bool {
+    let mut diagnostics = Diagnostics::default();
+    let tokens = Scanner::new(source).scan_tokens(&mut diagnostics).clone();
+
+    for diagnostic in diagnostics.drain() {
+        let code = diagnostic.code;
+
+        if code == Some(codes::UNTERMINATED_STRING.code)
+            || code == Some(codes::UNTERMINATED_COMMENT.code)
+        {
+            return false;
+        }
+    }
+
+    let mut depth = 0;
+
+    for token in &tokens {
+        match token.token_type {
+            TokenType::LeftParen | TokenType::LeftBrace | TokenType::LeftBracket => depth += 1,
+            TokenType::RightParen | TokenType::RightBrace | TokenType::RightBracket => depth -= 1,
+            _ => {}
+        }
+    }
+
+    if depth != 0 {
+        return depth < 0;
+    }
+
+    // the parser only gets to the end of the input when everything before it was fine, so an
+    // error there means something is missing, most often a `;`
+    let end = tokens.last().map_or(source.len(), |eof| eof.offset);
+    let mut diagnostics = Diagnostics::default();
+
+    Parser::new(&tokens).parse(&mut diagnostics);
+
+    let first_error = diagnostics.drain().find(|diagnostic| diagnostic.is_error());
+
+    !matches!(first_error.and_then(|error| error.span), Some(span) if span.offset >= end)
+}
+
+/// Bare expressions may leave out the final `;`, so typing `1 + 2` shows `=> 3`. Returns the
+/// input, with the `;` added if it was left out, when it ends in an expression statement.
+pub fn terminate_expression(source: &str) -> Option<String> {
+    let source = source.trim_end();
+
+    [format!("{}\n", source), format!("{};\n", source)]
+        .iter()
+        .find(|candidate| ends_in_expression(candidate))
+        .cloned()
+}
+
+fn ends_in_expression(source: &str) -> bool {
+    let mut diagnostics = Diagnostics::default();
+    let statements = parse_source(source, &mut diagnostics);
+
+    matches!(statements.last(), Some(Stmt::Expression { .. })) && !diagnostics.has_errors()
+}
+
+/// Whether evaluating `expr` leaves everything as it was: no calls, assignments or field writes
+pub fn is_pure(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal { .. } | Expr::Variable { .. } | Expr::This { .. } => true,
+        Expr::Grouping { expression, .. } => is_pure(expression),
+        Expr::Unary { right, .. } => is_pure(right),
+        Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+            is_pure(left) && is_pure(right)
+        }
+        Expr::List { elements, .. } => elements.iter().all(is_pure),
+        Expr::Get { object, .. } => is_pure(object),
+        Expr::Assign { .. } | Expr::Call { .. } | Expr::Set { .. } | Expr::Super { .. } => false,
+    }
+}
+
+/// Lines starting with `:` inspect or control the session instead of being run as Lox
+#[derive(Debug, PartialEq)]
+pub enum Command<'a> {
+    Help,
+    Env,
+    Type(&'a str),
+    Ast(&'a str),
+    Tokens(&'a str),
+    Load(&'a str),
+    Reset,
+    Time(&'a str),
+    Save(&'a str),
+}
+
+/// Every command with its argument and what it does, shown by `:help`
+pub const COMMANDS: &[(&str, &str, &str)] = &[
+    (":help", "", "show this list"),
+    (":env", "", "list the variables in scope and their values"),
+    (
+        ":type",
+        "expr",
+        "show the type of an expression without running calls or assignments",
+    ),
+    (":ast", "expr", "show the syntax tree of an expression"),
+    (":tokens", "src", "show the tokens source is scanned into"),
+    (":load", "file", "run a file in this session"),
+    (":reset", "", "start over with a fresh interpreter"),
+    (":time", "expr", "run input and show how long it took"),
+    (":save", "file", "write the input accepted so far to a file"),
+];
+
+impl<'a> Command<'a> {
+    /// None when `line` isn't a command but Lox source
+    pub fn parse(line: &'a str) -> Option<Result<Self, String>> {
+        let line = line.trim();
+
+        if !line.starts_with(':') {
+            return None;
+        }
+
+        let (name, argument) = match line.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (line, ""),
+        };
+
+        let command = match name {
+            ":help" => Command::Help,
+            ":env" => Command::Env,
+            ":type" => Command::Type(argument),
+            ":ast" => Command::Ast(argument),
+            ":tokens" => Command::Tokens(argument),
+            ":load" => Command::Load(argument),
+            ":reset" => Command::Reset,
+            ":time" => Command::Time(argument),
+            ":save" => Command::Save(argument),
+            _ => return Some(Err(format!("Unknown command '{}', try :help.", name))),
+        };
+
+        let takes_argument = COMMANDS
+            .iter()
+            .any(|(command, argument, _)| *command == name && !argument.is_empty());
+
+        Some(match (takes_argument, argument.is_empty()) {
+            (true, true) => Err(format!("{} needs an argument, try :help.", name)),
+            (false, false) => Err(format!("{} takes no argument.", name)),
+            _ => Ok(command),
+        })
+    }
+}
+
+/// Completes names and commands on Tab and shows the parameters of a function once its `(` is
+/// typed. Knows what the session defined as of the last `update`.
+#[derive(Default)]
+pub struct ReplHelper {
+    names: Vec<String>,
+    // parameter list of every function in scope
+    signatures: HashMap<String, String>,
+}
+
+impl ReplHelper {
+    /// Called with the bindings in scope before each line is read
+    pub fn update(&mut self, bindings: &[(String, Object)]) {
+        self.names = bindings.iter().map(|(name, _)| name.clone()).collect();
+        self.signatures = bindings
+            .iter()
+            .filter_map(|(name, value)| match value {
+                Object::Callable(function) => Some((name.clone(), function.param_list())),
+                _ => None,
+            })
+            .collect();
+    }
+
+    // where the completed word starts and what it could be
+    fn completions(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let before = &line[..pos];
+
+        if before.starts_with(':') && !before.contains(char::is_whitespace) {
+            let commands = COMMANDS
+                .iter()
+                .map(|(name, _, _)| name.to_string())
+                .filter(|name| name.starts_with(before))
+                .collect();
+
+            return (0, commands);
+        }
+
+        let start = word_start(before);
+        let prefix = &before[start..];
+
+        // fields and methods need classes, which don't exist yet
+        if before[..start].ends_with('.') {
+            return (start, Vec::new());
+        }
+
+        let mut candidates = self
+            .names
+            .iter()
+            .map(String::as_str)
+            .chain(KEYWORDS.keys().copied())
+            .filter(|candidate| candidate.starts_with(prefix))
+            .map(str::to_owned)
+            .collect::<Vec<_>>();
+
+        candidates.sort();
+        candidates.dedup();
+
+        (start, candidates)
+    }
+
+    // `(a, b)` right after `add(` was typed
+    fn signature_hint(&self, line: &str, pos: usize) -> Option<String> {
+        if pos < line.len() {
+            return None;
+        }
+
+        let callee = line.strip_suffix('(')?;
+        let name = &callee[word_start(callee)..];
+
+        self.signatures
+            .get(name)
+            .map(|params| format!("{})", params))
+    }
+}
+
+// byte offset of the identifier `text` ends with
+fn word_start(text: &str) -> usize {
+    text.char_indices()
+        .rev()
+        .take_while(|(_, c)| UnicodeXID::is_xid_continue(*c))
+        .last()
+        .map_or(text.len(), |(start, _)| start)
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(self.completions(line, pos))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<String> {
+        self.signature_hint(line, pos)
+    }
+}
+
+impl Highlighter for ReplHelper {
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        // dimmed so it doesn't read as typed text
+        Cow::Owned(format!("\x1b[2m{}\x1b[0m", hint))
+    }
+}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+#[cfg(test)]
+mod test {
+    use super::{is_complete, is_pure, terminate_expression, Command, ReplHelper};
+
+    use crate::diagnostic::Diagnostics;
+    use crate::interpreter::Interpreter;
+    use crate::parser::parse_source;
+    use crate::statement::Stmt;
+
+    fn helper(source: &str) -> ReplHelper {
+        let statements = parse_source(source, &mut Diagnostics::default());
+
+        let mut interpreter = Interpreter::new();
+        interpreter.interpret(&statements).unwrap();
+
+        let mut helper = ReplHelper::default();
+        helper.update(&interpreter.bindings());
+
+        helper
+    }
+
+    #[test]
+    fn complete_input() {
+        assert!(is_complete(""));
+        assert!(is_complete("print 1;"));
+        assert!(is_complete("fun f() {\n  return 1;\n}"));
+        assert!(is_complete("var a = [1, 2];"));
+    }
+
+    #[test]
+    fn incomplete_input() {
+        assert!(!is_complete("fun f() {"));
+        assert!(!is_complete("print (1 +"));
+        assert!(!is_complete("var a = [1,"));
+        assert!(!is_complete("print 1"));
+        assert!(!is_complete("print \"abc"));
+        assert!(!is_complete("/* comment"));
+        assert!(!is_complete("if (true) print 1; else"));
+    }
+
+    #[test]
+    fn errors_more_lines_cannot_fix_are_complete() {
+        assert!(is_complete("print 1;)"));
+        assert!(is_complete("print ) 1;"));
+        assert!(is_complete("var 1 = 2"));
+    }
+
+    #[test]
+    fn expressions_may_leave_out_the_semicolon() {
+        assert_eq!(terminate_expression("1 + 2\n"), Some("1 + 2;\n".to_owned()));
+        assert_eq!(
+            terminate_expression("var a = 1; a\n"),
+            Some("var a = 1; a;\n".to_owned())
+        );
+        assert_eq!(terminate_expression("1;"), Some("1;\n".to_owned()));
+        assert_eq!(terminate_expression("print 1\n"), None);
+        assert_eq!(terminate_expression("var a = 1\n"), None);
+        assert_eq!(terminate_expression("1 +\n"), None);
+    }
+
+    #[test]
+    fn pure_expressions() {
+        let expression =
+            |source: &str| match parse_source(source, &mut Diagnostics::default()).pop() {
+                Some(Stmt::Expression { value }) => value,
+                statement => panic!("expected an expression, got {:?}", statement),
+            };
+
+        assert!(is_pure(&expression("-a + [1, b] == (c or !d);")));
+        assert!(!is_pure(&expression("launch();")));
+        assert!(!is_pure(&expression("x = 5;")));
+        assert!(!is_pure(&expression("[1, (f(2))];")));
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(Command::parse("print 1;"), None);
+        assert_eq!(Command::parse(":env"), Some(Ok(Command::Env)));
+        assert_eq!(
+            Command::parse("  :type  1 + 2 "),
+            Some(Ok(Command::Type("1 + 2")))
+        );
+        assert_eq!(
+            Command::parse(":load a b.lox"),
+            Some(Ok(Command::Load("a b.lox")))
+        );
+        assert!(matches!(Command::parse(":type"), Some(Err(_))));
+        assert!(matches!(Command::parse(":reset now"), Some(Err(_))));
+        assert!(matches!(Command::parse(":quit"), Some(Err(_))));
+    }
+
+    #[test]
+    fn completes_names_keywords_and_commands() {
+        let helper = helper("var counter = 1; var country = 2; fun count(a, b) {}");
+
+        assert_eq!(
+            helper.completions("print coun", 10),
+            (
+                6,
+                vec![
+                    "count".to_owned(),
+                    "counter".to_owned(),
+                    "country".to_owned()
+                ]
+            )
+        );
+        assert_eq!(helper.completions("whi", 3), (0, vec!["while".to_owned()]));
+        assert_eq!(helper.completions("x.coun", 6), (2, Vec::new()));
+        assert_eq!(
+            helper.completions(":to", 3),
+            (0, vec![":tokens".to_owned()])
+        );
+    }
+
+    #[test]
+    fn hints_parameters_after_paren() {
+        let helper = helper("fun add(a, b) { return a + b; } var n = 1;");
+
+        assert_eq!(
+            helper.signature_hint("print add(", 10),
+            Some("a, b)".to_owned())
+        );
+        assert_eq!(helper.signature_hint("clock(", 6), Some(")".to_owned()));
+        assert_eq!(helper.signature_hint("n(", 2), None);
+        assert_eq!(helper.signature_hint("add(1", 5), None);
+    }
+}
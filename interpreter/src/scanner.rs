@@ -1,11 +1,14 @@
-use anyhow::{anyhow, Result};
 use phf::phf_map;
+use unicode_xid::UnicodeXID;
 
-use crate::lox::Lox;
+use crate::codes::{self, ErrorCode};
+use crate::diagnostic::{Diagnostic, Diagnostics};
+use crate::span::Span;
 use crate::token::{Token, TokenType};
 
-static KEYWORDS: phf::Map<&'static str, TokenType> = phf_map! {
+pub(crate) static KEYWORDS: phf::Map<&'static str, TokenType> = phf_map! {
     "and" => TokenType::And,
+    "assert" => TokenType::Assert,
     "class" => TokenType::Class,
     "else" => TokenType::Else,
     "false" => TokenType::False,
@@ -16,6 +19,7 @@ static KEYWORDS: phf::Map<&'static str, TokenType> = phf_map! {
     "or" => TokenType::Or,
     "print" => TokenType::Print,
     "return" => TokenType::Return,
+    "spawn" => TokenType::Spawn,
     "super" => TokenType::Super,
     "this" => TokenType::This,
     "true" => TokenType::True,
@@ -23,6 +27,9 @@ static KEYWORDS: phf::Map<&'static str, TokenType> = phf_map! {
     "while" => TokenType::While,
 };
 
+// error code and message, turned into a diagnostic once the token's span is known
+type ScanResult = Result<(), (&'static ErrorCode, String)>;
+
 pub struct Scanner {
     source: Vec<char>,
     tokens: Vec<Token>,
@@ -31,6 +38,16 @@ pub struct Scanner {
     start: usize,
     current: usize,
     line: i32,
+
+    // positions are counted in chars, offsets in UTF-8 bytes
+    line_start: usize,
+    current_offset: usize,
+    start_line: i32,
+    start_column: usize,
+    start_offset: usize,
+
+    // doc comment lines waiting for the next token
+    doc: Vec<String>,
 }
 
 impl Scanner {
@@ -42,6 +59,25 @@ impl Scanner {
             start: 0,
             current: 0,
             line: 1,
+
+            line_start: 0,
+            current_offset: 0,
+            start_line: 1,
+            start_column: 1,
+            start_offset: 0,
+
+            doc: vec![],
+        }
+    }
+
+    // for source that continues some earlier code, like REPL input
+    pub fn with_position(source: &str, line: i32, offset: usize) -> Self {
+        Self {
+            line,
+            current_offset: offset,
+            start_line: line,
+            start_offset: offset,
+            ..Self::new(source)
         }
     }
 
@@ -49,30 +85,58 @@ impl Scanner {
         self.current >= self.source.len()
     }
 
+    // returns '\0' without moving once the source is exhausted, like peek
     fn advance(&mut self) -> char {
-        let c = self.source.get(self.current).unwrap_or_else(|| {
-            // clippy does not like expect() here for some reason
-            panic!("advanced into exhausted source at index {}", self.current)
-        });
+        let c = match self.source.get(self.current) {
+            Some(&c) => c,
+            None => return '\0',
+        };
 
         self.current += 1;
+        self.current_offset += c.len_utf8();
 
-        *c
+        if c == '\n' {
+            self.line += 1;
+            self.line_start = self.current;
+        }
+
+        c
+    }
+
+    fn start_token(&mut self) {
+        self.start = self.current;
+        self.start_line = self.line;
+        self.start_column = self.current - self.line_start + 1;
+        self.start_offset = self.current_offset;
     }
 
     fn current_lexeme(&self) -> String {
         self.source
-            .get(self.start as usize..self.current as usize)
-            .unwrap()
+            .get(self.start..self.current)
+            .unwrap_or_default()
             .iter()
             .collect()
     }
 
+    fn take_doc(&mut self) -> Option<String> {
+        if self.doc.is_empty() {
+            None
+        } else {
+            Some(self.doc.drain(..).collect::<Vec<_>>().join("\n"))
+        }
+    }
+
     fn add_token(&mut self, token: TokenType) {
+        let doc = self.take_doc();
+
         self.tokens.push(Token {
             token_type: token,
             lexeme: self.current_lexeme(),
-            line: self.line,
+            line: self.start_line,
+            column: self.start_column,
+            offset: self.start_offset,
+            length: self.current_offset - self.start_offset,
+            doc,
         });
     }
 
@@ -95,22 +159,21 @@ impl Scanner {
             return false;
         };
 
-        self.current += 1;
+        self.advance();
 
         true
     }
 
-    fn parse_string(&mut self) -> Result<()> {
+    fn parse_string(&mut self) -> ScanResult {
         while self.peek(0) != '"' && !self.is_at_end() {
-            if self.peek(0) == '\n' {
-                self.line += 1;
-            }
-
             self.advance();
         }
 
         if self.is_at_end() {
-            return Err(anyhow!("Unterminated string."));
+            return Err((
+                codes::UNTERMINATED_STRING,
+                "Unterminated string.".to_owned(),
+            ));
         }
 
         self.advance();
@@ -119,24 +182,73 @@ impl Scanner {
 
         self.add_token(TokenType::String {
             literal: current_lexeme
-                .get(1..current_lexeme.len() - 1)
-                .unwrap()
+                .strip_prefix('"')
+                .and_then(|lexeme| lexeme.strip_suffix('"'))
+                .unwrap_or_default()
                 .to_owned(),
         });
 
         Ok(())
     }
 
+    fn parse_line_comment(&mut self) {
+        // `///` is a doc comment, but `////` is a regular one
+        let is_doc = self.peek(0) == '/' && self.peek(1) != '/';
+
+        while self.peek(0) != '\n' && !self.is_at_end() {
+            self.advance();
+        }
+
+        if is_doc {
+            let lexeme = self.current_lexeme();
+            let text = &lexeme[3..];
+
+            self.doc
+                .push(text.strip_prefix(' ').unwrap_or(text).trim_end().to_owned());
+        }
+    }
+
+    fn parse_block_comment(&mut self) -> ScanResult {
+        let mut depth = 1;
+
+        while depth > 0 {
+            if self.is_at_end() {
+                return Err((
+                    codes::UNTERMINATED_COMMENT,
+                    "Unterminated block comment.".to_owned(),
+                ));
+            }
+
+            match self.advance() {
+                '/' if self.peek(0) == '*' => {
+                    self.advance();
+                    depth += 1;
+                }
+                '*' if self.peek(0) == '/' => {
+                    self.advance();
+                    depth -= 1;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
     fn is_digit(c: char) -> bool {
-        c.is_digit(10)
+        c.is_ascii_digit()
     }
 
-    fn parse_number(&mut self) {
+    fn parse_number(&mut self) -> ScanResult {
         while Self::is_digit(self.peek(0)) {
             self.advance();
         }
 
+        let mut is_float = false;
+
         if self.peek(0) == '.' && Self::is_digit(self.peek(1)) {
+            is_float = true;
+
             self.advance();
         }
 
@@ -144,20 +256,48 @@ impl Scanner {
             self.advance();
         }
 
-        self.add_token(TokenType::Number {
-            literal: self
-                .current_lexeme()
-                .parse()
-                .expect("could not parse float"),
-        })
+        let lexeme = self.current_lexeme();
+        let out_of_range = || {
+            (
+                codes::LITERAL_OUT_OF_RANGE,
+                "Number literal is out of range.".to_owned(),
+            )
+        };
+
+        // 1.10d
+        if self.peek(0) == 'd' && !Self::is_alphanumeric(self.peek(1)) {
+            self.advance();
+
+            self.add_token(TokenType::Decimal {
+                literal: lexeme.parse().map_err(|_| {
+                    (
+                        codes::LITERAL_OUT_OF_RANGE,
+                        "Decimal literal is out of range.".to_owned(),
+                    )
+                })?,
+            });
+        } else if is_float {
+            self.add_token(TokenType::Number {
+                literal: lexeme.parse().map_err(|_| out_of_range())?,
+            });
+        } else {
+            match lexeme.parse() {
+                Ok(literal) => self.add_token(TokenType::Integer { literal }),
+                Err(_) => self.add_token(TokenType::BigInteger {
+                    literal: lexeme.parse().map_err(|_| out_of_range())?,
+                }),
+            }
+        }
+
+        Ok(())
     }
 
     fn is_alpha(c: char) -> bool {
-        c.is_ascii_alphabetic() || c == '_'
+        c == '_' || UnicodeXID::is_xid_start(c)
     }
 
     fn is_alphanumeric(c: char) -> bool {
-        c.is_ascii_alphanumeric() || c == '_'
+        UnicodeXID::is_xid_continue(c)
     }
 
     fn parse_identifier(&mut self) {
@@ -174,7 +314,7 @@ impl Scanner {
         })
     }
 
-    fn scan_token(&mut self) -> Result<()> {
+    fn scan_token(&mut self) -> ScanResult {
         let c = self.advance();
 
         let maybe_token = match c {
@@ -182,7 +322,10 @@ impl Scanner {
             ')' => Some(TokenType::RightParen),
             '{' => Some(TokenType::LeftBrace),
             '}' => Some(TokenType::RightBrace),
+            '[' => Some(TokenType::LeftBracket),
+            ']' => Some(TokenType::RightBracket),
             ',' => Some(TokenType::Comma),
+            ':' => Some(TokenType::Colon),
             '.' => Some(TokenType::Dot),
             '-' => Some(TokenType::Minus),
             '+' => Some(TokenType::Plus),
@@ -218,27 +361,23 @@ impl Scanner {
             }
             '/' => {
                 if self.match_('/') {
-                    while self.peek(0) != '\n' && !self.is_at_end() {
-                        self.advance();
-                    }
-
+                    self.parse_line_comment();
+                    None
+                } else if self.match_('*') {
+                    self.parse_block_comment()?;
                     None
                 } else {
                     Some(TokenType::Slash)
                 }
             }
-            ' ' | '\r' | '\t' => None,
+            ' ' | '\r' | '\t' | '\n' => None,
             '"' => {
                 self.parse_string()?;
                 None
             }
-            '\n' => {
-                self.line += 1;
-                None
-            }
             _ => {
                 if Self::is_digit(c) {
-                    self.parse_number();
+                    self.parse_number()?;
                     None
                 } else if Self::is_alpha(c) {
                     self.parse_identifier();
@@ -251,7 +390,10 @@ impl Scanner {
 
         if let Some(token) = maybe_token {
             if token == TokenType::Unknown {
-                Err(anyhow!("Unexpected character: {}", c))
+                Err((
+                    codes::UNEXPECTED_CHARACTER,
+                    format!("Unexpected character: {}", c),
+                ))
             } else {
                 self.add_token(token);
 
@@ -262,20 +404,132 @@ impl Scanner {
         }
     }
 
-    pub fn scan_tokens<'a>(&mut self, lox: &'a mut Lox) -> &Vec<Token> {
+    pub fn scan_tokens(&mut self, diagnostics: &mut Diagnostics) -> &Vec<Token> {
+        // `#!/usr/bin/env yaliir` on the first line makes a script executable
+        if self.current == 0 && self.source.starts_with(&['#', '!']) {
+            while !self.is_at_end() && self.peek(0) != '\n' {
+                self.advance();
+            }
+        }
+
         while !self.is_at_end() {
-            self.start = self.current;
+            self.start_token();
 
-            if let Err(err) = self.scan_token() {
-                lox.error(self.line, &err.to_string());
+            if let Err((code, message)) = self.scan_token() {
+                let span = Span {
+                    offset: self.start_offset,
+                    length: self.current_offset - self.start_offset,
+                    line: self.start_line,
+                    column: self.start_column,
+                };
+
+                diagnostics.push(Diagnostic::error(Some(span), message).with_code(code));
             }
         }
+
+        self.start_token();
+
+        // right after the last token, errors about missing code look better there
+        let (line, column, offset) = match self.tokens.last() {
+            Some(last) if !last.lexeme.contains('\n') => (
+                last.line,
+                last.column + last.lexeme.chars().count(),
+                last.offset + last.length,
+            ),
+            _ => (self.line, self.start_column, self.start_offset),
+        };
+
+        // a doc comment at the very end has nothing to document, kept so it can be reported
+        let doc = self.take_doc();
+
         self.tokens.push(Token {
             token_type: TokenType::Eof,
             lexeme: "".to_owned(),
-            line: self.line,
+            line,
+            column,
+            offset,
+            length: 0,
+            doc,
         });
 
         &self.tokens
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::Scanner;
+
+    use crate::diagnostic::Diagnostics;
+    use crate::token::TokenType;
+
+    #[test]
+    fn nested_block_comment() {
+        let mut diagnostics = Diagnostics::default();
+        let tokens = Scanner::new("/* outer /* inner\n */ still comment */ 1")
+            .scan_tokens(&mut diagnostics)
+            .clone();
+
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[0].token_type, TokenType::Integer { literal: 1 });
+        assert_eq!(tokens[0].line, 2);
+    }
+
+    #[test]
+    fn shebang_line_is_skipped() {
+        let mut diagnostics = Diagnostics::default();
+        let tokens = Scanner::new("#!/usr/bin/env yaliir --no-asserts\nprint 1;")
+            .scan_tokens(&mut diagnostics)
+            .clone();
+
+        assert!(!diagnostics.has_errors());
+        assert_eq!(tokens[0].token_type, TokenType::Print);
+        assert_eq!((tokens[0].line, tokens[0].column), (2, 1));
+
+        // also when the source continues a session, like a file loaded into the REPL
+        let tokens = Scanner::with_position("#!/usr/bin/env yaliir\nprint 1;", 7, 120)
+            .scan_tokens(&mut diagnostics)
+            .clone();
+
+        assert!(!diagnostics.has_errors());
+        assert_eq!(tokens[0].token_type, TokenType::Print);
+
+        // only on the first line
+        Scanner::new("print 1;\n#!/bin/sh").scan_tokens(&mut diagnostics);
+
+        assert!(diagnostics.has_errors());
+    }
+
+    #[test]
+    fn doc_comment_attaches_to_next_token() {
+        let mut diagnostics = Diagnostics::default();
+        let tokens = Scanner::new("/// Adds numbers.\n/// Returns sum.\n//// not a doc\nfun")
+            .scan_tokens(&mut diagnostics)
+            .clone();
+
+        assert_eq!(tokens[0].token_type, TokenType::Fun);
+        assert_eq!(
+            tokens[0].doc.as_deref(),
+            Some("Adds numbers.\nReturns sum.")
+        );
+    }
+
+    #[test]
+    fn unicode_identifiers_and_positions() {
+        let mut diagnostics = Diagnostics::default();
+        let tokens = Scanner::new("var имя = \"ü\";\n  日本 = 1;")
+            .scan_tokens(&mut diagnostics)
+            .clone();
+
+        assert_eq!(tokens[1].token_type, TokenType::Identifier);
+        assert_eq!(tokens[1].lexeme, "имя");
+        assert_eq!((tokens[1].column, tokens[1].offset), (5, 4));
+        assert_eq!((tokens[3].column, tokens[3].offset), (11, 13));
+        assert_eq!(tokens[5].lexeme, "日本");
+        assert_eq!(
+            (tokens[5].line, tokens[5].column, tokens[5].offset),
+            (2, 3, 21)
+        );
+        assert_eq!((tokens[6].column, tokens[6].offset), (6, 28));
+    }
+}
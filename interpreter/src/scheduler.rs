@@ -0,0 +1,238 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+
+use corosensei::stack::DefaultStack;
+use corosensei::{Coroutine, CoroutineResult, Yielder};
+
+use crate::error::LoxError;
+use crate::function::Function;
+use crate::interpreter::{Frame, Interpreter, STACK_SIZE};
+use crate::object::Object;
+use crate::token::Token;
+
+pub(crate) type TaskId = usize;
+type TaskYielder = Yielder<(), ()>;
+// a finished task hands back its result and the call stack it failed with
+type TaskCoroutine =
+    Coroutine<(), (), (Result<Object, LoxError>, Option<Vec<Frame>>), DefaultStack>;
+
+pub type SharedChannel = Rc<RefCell<Channel>>;
+
+#[derive(Debug, Default)]
+pub struct Channel {
+    buffer: VecDeque<Object>,
+    // tasks suspended in recv() on this channel
+    waiters: Vec<TaskId>,
+}
+
+struct Task {
+    // taken out of here while the task is running
+    coroutine: Option<TaskCoroutine>,
+}
+
+// Cooperative single threaded scheduler. Every task runs on its own stack with an interpreter of
+// its own and is only ever resumed from the main script stack, tasks suspend back to it when they
+// block or yield. Ready tasks run in spawn order, which keeps execution deterministic.
+#[derive(Default)]
+pub struct Scheduler {
+    next_id: TaskId,
+    tasks: HashMap<TaskId, Task>,
+    ready: VecDeque<TaskId>,
+}
+
+impl Scheduler {
+    pub(crate) fn wake(&mut self, tasks: impl Iterator<Item = TaskId>) {
+        self.ready.extend(tasks);
+    }
+}
+
+// Set on the interpreter of a running task
+pub(crate) struct TaskContext {
+    pub(crate) id: TaskId,
+    // lives on the stack of the task for as long as the task runs
+    yielder: *const TaskYielder,
+}
+
+impl TaskContext {
+    // hands control back to the main script until the task is resumed
+    pub(crate) fn suspend(&self) {
+        // SAFETY: only the task itself reaches its context, while its stack and with it the
+        // yielder are alive
+        unsafe { (*self.yielder).suspend(()) }
+    }
+}
+
+impl Interpreter {
+    pub(crate) fn spawn(
+        &mut self,
+        function: Function,
+        arguments: Vec<Object>,
+        call_site: Option<Token>,
+    ) -> Result<(), LoxError> {
+        let stack = DefaultStack::new(STACK_SIZE)
+            .map_err(|err| LoxError::Error(format!("Unable to allocate task stack: {}", err)))?;
+
+        let mut scheduler = self.scheduler();
+
+        let id = scheduler.next_id;
+        scheduler.next_id += 1;
+
+        let shared = Rc::clone(&self.shared);
+        let asserts_enabled = self.asserts_enabled;
+
+        let coroutine = Coroutine::with_stack(stack, move |yielder: &TaskYielder, ()| {
+            let context = TaskContext { id, yielder };
+            let mut task = Interpreter::with_shared(shared, Some(context), asserts_enabled);
+
+            let result = task.call_function(&function, call_site.as_ref(), &arguments);
+
+            (result, task.trace.take())
+        });
+
+        scheduler.tasks.insert(
+            id,
+            Task {
+                coroutine: Some(coroutine),
+            },
+        );
+        scheduler.ready.push_back(id);
+
+        Ok(())
+    }
+
+    fn resume_task(&mut self, id: TaskId) -> Result<(), LoxError> {
+        let coroutine = self
+            .scheduler()
+            .tasks
+            .get_mut(&id)
+            .and_then(|task| task.coroutine.take());
+
+        let mut coroutine = match coroutine {
+            Some(coroutine) => coroutine,
+            // already finished
+            None => return Ok(()),
+        };
+
+        // the scheduler must not stay borrowed in here, the task uses it too
+        match coroutine.resume(()) {
+            CoroutineResult::Yield(()) => {
+                if let Some(task) = self.scheduler().tasks.get_mut(&id) {
+                    task.coroutine = Some(coroutine);
+                }
+
+                Ok(())
+            }
+            CoroutineResult::Return((result, trace)) => {
+                self.scheduler().tasks.remove(&id);
+
+                if result.is_err() && self.trace.is_none() {
+                    self.trace = trace;
+                }
+
+                result.map(|_| ())
+            }
+        }
+    }
+
+    // runs tasks until `done` holds, returns false if nothing is left to run before that
+    pub(crate) fn run_tasks_until(
+        &mut self,
+        done: impl Fn(&Interpreter) -> bool,
+    ) -> Result<bool, LoxError> {
+        while !done(self) {
+            let next = self.scheduler().ready.pop_front();
+
+            match next {
+                Some(id) => self.resume_task(id)?,
+                None => return Ok(false),
+            }
+        }
+
+        Ok(true)
+    }
+
+    // lets spawned tasks run until they finish or block
+    pub(crate) fn run_ready_tasks(&mut self) -> Result<(), LoxError> {
+        self.run_tasks_until(|_| false).map(|_| ())
+    }
+
+    // tasks left over once there is nothing to wake them up are stuck for good
+    pub(crate) fn check_deadlock(&mut self) -> Result<(), LoxError> {
+        let blocked = self.scheduler().tasks.len();
+
+        if blocked > 0 {
+            return Err(LoxError::Deadlock(format!(
+                "Deadlock: {} task(s) blocked forever.",
+                blocked
+            )));
+        }
+
+        Ok(())
+    }
+
+    // drops all tasks, used after an error
+    pub(crate) fn reset_tasks(&mut self) {
+        // suspended tasks unwind their stacks when dropped, not while the scheduler is borrowed
+        let scheduler = self.shared.scheduler.replace(Scheduler::default());
+
+        drop(scheduler);
+    }
+
+    pub(crate) fn send(&mut self, channel: &SharedChannel, value: Object) {
+        let mut channel = channel.borrow_mut();
+
+        channel.buffer.push_back(value);
+
+        // woken tasks check the buffer again, so waking everyone is fine
+        self.scheduler().wake(channel.waiters.drain(..));
+    }
+
+    pub(crate) fn receive(&mut self, channel: &SharedChannel) -> Result<Object, LoxError> {
+        loop {
+            if let Some(value) = channel.borrow_mut().buffer.pop_front() {
+                return Ok(value);
+            }
+
+            match &self.task {
+                Some(task) => {
+                    channel.borrow_mut().waiters.push(task.id);
+
+                    task.suspend();
+                }
+                None => {
+                    if !self.run_until(|_| !channel.borrow().buffer.is_empty())? {
+                        return Err(LoxError::Deadlock(
+                            "Deadlock: all tasks are blocked while receiving from a channel."
+                                .to_owned(),
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    pub(crate) fn yield_now(&mut self) -> Result<(), LoxError> {
+        match &self.task {
+            Some(task) => {
+                self.scheduler().ready.push_back(task.id);
+
+                task.suspend();
+            }
+            None => {
+                // every task that is ready right now gets one turn
+                let ready = self.scheduler().ready.len();
+
+                for _ in 0..ready {
+                    let next = self.scheduler().ready.pop_front();
+
+                    if let Some(id) = next {
+                        self.resume_task(id)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
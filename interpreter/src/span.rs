@@ -0,0 +1,221 @@
+use std::fmt::Write;
+
+// A piece of source code, offsets and lengths are in bytes, columns in chars starting from 1
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Span {
+    pub offset: usize,
+    pub length: usize,
+    pub line: i32,
+    pub column: usize,
+}
+
+impl Span {
+    // everything from the start of this span to the end of `other`
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            length: (other.offset + other.length).max(self.offset + self.length) - self.offset,
+            ..self
+        }
+    }
+}
+
+// secondary message pointing at a related piece of code
+#[derive(Clone, Debug)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+impl Label {
+    pub fn new(span: Span, message: impl Into<String>) -> Self {
+        Self {
+            span,
+            message: message.into(),
+        }
+    }
+}
+
+// Renders a message rustc style: a header, then every involved source line with the primary span
+// underlined by carets and labels underlined by dashes. `file` is what `source` was read from.
+pub fn render(
+    source: &str,
+    file: Option<&str>,
+    severity: &str,
+    message: &str,
+    span: Span,
+    labels: &[Label],
+) -> String {
+    let mut out = format!("{}: {}\n", severity, message);
+
+    let mut marks = vec![(span, '^', "")];
+    marks.extend(
+        labels
+            .iter()
+            .map(|label| (label.span, '-', label.message.as_str())),
+    );
+    // spans from some other source cannot be shown
+    marks.retain(|(span, _, _)| source.is_char_boundary(span.offset.min(source.len() + 1)));
+    marks.sort_by_key(|(span, _, _)| span.line);
+
+    let gutter = marks
+        .iter()
+        .map(|(span, _, _)| span.line.to_string().len())
+        .max()
+        .unwrap_or(1);
+
+    let location = match file {
+        Some(file) => format!("{}:{}:{}", file, span.line, span.column),
+        None => format!("{}:{}", span.line, span.column),
+    };
+
+    let _ = writeln!(out, "{:gutter$}--> {}", "", location);
+
+    let mut current_line = None;
+
+    for (span, mark, text) in marks {
+        let line_start = source[..span.offset].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[span.offset..]
+            .find('\n')
+            .map_or(source.len(), |i| span.offset + i);
+
+        if current_line != Some(span.line) {
+            let _ = writeln!(out, "{:gutter$} |", "");
+            let line = format!("{:>gutter$} | {}", span.line, &source[line_start..line_end]);
+
+            let _ = writeln!(out, "{}", line.trim_end());
+
+            current_line = Some(span.line);
+        }
+
+        // the end of a span from some other source can be inside a char here
+        let mut end = span.offset.saturating_add(span.length).min(line_end);
+
+        while !source.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        let underlined = source[span.offset..end].chars().count().max(1);
+
+        let underline = format!(
+            "{:gutter$} | {}{} {}",
+            "",
+            " ".repeat(span.column.saturating_sub(1)),
+            mark.to_string().repeat(underlined),
+            text
+        );
+
+        let _ = writeln!(out, "{}", underline.trim_end());
+    }
+
+    out.trim_end().to_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{render, Label, Span};
+
+    #[test]
+    fn render_with_labels() {
+        let source = "fun f(a) {}\nprint 1 + \"a\";\n";
+
+        let rendered = render(
+            source,
+            None,
+            "error",
+            "Operands must be two numbers or two strings.",
+            Span {
+                offset: 20,
+                length: 1,
+                line: 2,
+                column: 9,
+            },
+            &[
+                Label::new(
+                    Span {
+                        offset: 18,
+                        length: 1,
+                        line: 2,
+                        column: 7,
+                    },
+                    "int",
+                ),
+                Label::new(
+                    Span {
+                        offset: 4,
+                        length: 1,
+                        line: 1,
+                        column: 5,
+                    },
+                    "defined here",
+                ),
+            ],
+        );
+
+        assert_eq!(
+            rendered,
+            [
+                "error: Operands must be two numbers or two strings.",
+                " --> 2:9",
+                "  |",
+                "1 | fun f(a) {}",
+                "  |     - defined here",
+                "  |",
+                "2 | print 1 + \"a\";",
+                "  |         ^",
+                "  |       - int",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn render_with_file() {
+        let span = Span {
+            offset: 6,
+            length: 1,
+            line: 1,
+            column: 7,
+        };
+
+        let rendered = render(
+            "print x;",
+            Some("tool.lox"),
+            "error",
+            "Undefined.",
+            span,
+            &[],
+        );
+
+        assert!(rendered.contains(" --> tool.lox:1:7\n"), "{}", rendered);
+    }
+
+    #[test]
+    fn span_ending_inside_a_char() {
+        // a label left over from other source, it starts on a char but ends inside `é`
+        let label = Label::new(
+            Span {
+                offset: 6,
+                length: 2,
+                line: 1,
+                column: 7,
+            },
+            "elsewhere",
+        );
+
+        let rendered = render(
+            "print \"é\";",
+            None,
+            "error",
+            "Oops.",
+            Span {
+                offset: 0,
+                length: 5,
+                line: 1,
+                column: 1,
+            },
+            &[label],
+        );
+
+        assert!(rendered.ends_with("|       - elsewhere"), "{}", rendered);
+    }
+}
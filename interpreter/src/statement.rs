@@ -1,24 +1,70 @@
 use crate::expression::Expr;
+use crate::span::Span;
 use crate::token::Token;
 
+/// Left hand side of a destructuring declaration: `var [a, b] = ...;` or `var {x, y} = ...;`.
+/// The parser rejects field patterns until there are classes.
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    Name(Token),
+    List {
+        bracket: Token,
+        elements: Vec<Pattern>,
+    },
+    Fields {
+        brace: Token,
+        names: Vec<Token>,
+    },
+}
+
+impl Pattern {
+    /// Token errors about this pattern should point at
+    pub fn token(&self) -> &Token {
+        match self {
+            Pattern::Name(name) => name,
+            Pattern::List { bracket, .. } => bracket,
+            Pattern::Fields { brace, .. } => brace,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum Stmt {
+    Assert {
+        keyword: Token,
+        condition: Expr,
+        message: Option<Expr>,
+        // condition as written, for error messages
+        source: String,
+    },
     Block {
         statements: Vec<Stmt>,
     },
+    Destructure {
+        pattern: Pattern,
+        initializer: Expr,
+    },
     Expression {
         value: Expr,
     },
     Function {
         name: Token,
         params: Vec<Token>,
+        // optional `: Type` annotations, one per parameter
+        param_types: Vec<Option<Token>>,
+        return_type: Option<Token>,
         body: Vec<Stmt>,
+        doc: Option<String>,
     },
     If {
         condition: Expr,
         then_branch: Box<Stmt>,
         else_branch: Option<Box<Stmt>>,
     },
+    MultiAssign {
+        targets: Vec<Token>,
+        values: Vec<Expr>,
+    },
     Print {
         value: Expr,
     },
@@ -26,8 +72,15 @@ pub enum Stmt {
         keyword: Token,
         value: Option<Expr>,
     },
+    Spawn {
+        keyword: Token,
+        callee: Expr,
+        paren: Token,
+        arguments: Vec<Expr>,
+    },
     Var {
         name: Token,
+        type_annotation: Option<Token>,
         initializer: Option<Expr>,
     },
     While {
@@ -37,36 +90,143 @@ pub enum Stmt {
 }
 
 impl Stmt {
+    /// Code this statement covers, None for an empty block. Statements do not keep their
+    /// keywords and semicolons around so this starts and ends at the closest token available.
+    pub fn span(&self) -> Option<Span> {
+        let until = |start: Span, end: Option<Span>| match end {
+            Some(end) => start.to(end),
+            None => start,
+        };
+
+        Some(match self {
+            Stmt::Assert {
+                keyword,
+                condition,
+                message,
+                ..
+            } => keyword
+                .span()
+                .to(message.as_ref().unwrap_or(condition).span()),
+            Stmt::Block { statements } => {
+                let first = statements.iter().find_map(Stmt::span)?;
+
+                until(first, statements.iter().rev().find_map(Stmt::span))
+            }
+            Stmt::Destructure {
+                pattern,
+                initializer,
+            } => pattern.token().span().to(initializer.span()),
+            Stmt::Expression { value } | Stmt::Print { value } => value.span(),
+            Stmt::Function { name, .. } => name.span(),
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => until(
+                condition.span(),
+                else_branch.as_ref().unwrap_or(then_branch).span(),
+            ),
+            Stmt::MultiAssign { targets, values } => {
+                until(targets.first()?.span(), values.last().map(Expr::span))
+            }
+            Stmt::Return { keyword, value } => {
+                until(keyword.span(), value.as_ref().map(Expr::span))
+            }
+            Stmt::Spawn { keyword, paren, .. } => keyword.span().to(paren.span()),
+            Stmt::Var {
+                name, initializer, ..
+            } => until(name.span(), initializer.as_ref().map(Expr::span)),
+            Stmt::While { condition, body } => until(condition.span(), body.span()),
+        })
+    }
+
     pub fn accept<R>(&self, visitor: &mut dyn Visitor<R>) -> R {
         match self {
+            Stmt::Assert {
+                keyword,
+                condition,
+                message,
+                source,
+            } => visitor.visit_assert(keyword, condition, message, source),
             Stmt::Block { statements } => visitor.visit_block(statements),
+            Stmt::Destructure {
+                pattern,
+                initializer,
+            } => visitor.visit_destructure(pattern, initializer),
             Stmt::Expression { value } => visitor.visit_expression(value),
-            Stmt::Function { name, params, body } => visitor.visit_function(name, params, body),
+            Stmt::Function {
+                name,
+                params,
+                param_types,
+                return_type,
+                body,
+                doc,
+            } => visitor.visit_function(name, params, param_types, return_type, body, doc),
             Stmt::If {
                 condition,
                 then_branch,
                 else_branch,
             } => visitor.visit_if(condition, then_branch, else_branch),
+            Stmt::MultiAssign { targets, values } => visitor.visit_multi_assign(targets, values),
             Stmt::Print { value } => visitor.visit_print(value),
             Stmt::Return { keyword, value } => visitor.visit_return(keyword, value),
-            Stmt::Var { name, initializer } => visitor.visit_var(name, initializer),
+            Stmt::Spawn {
+                keyword,
+                callee,
+                paren,
+                arguments,
+            } => visitor.visit_spawn(keyword, callee, paren, arguments),
+            Stmt::Var {
+                name,
+                type_annotation,
+                initializer,
+            } => visitor.visit_var(name, type_annotation, initializer),
             Stmt::While { condition, body } => visitor.visit_while(condition, body),
         }
     }
 }
 
 pub trait Visitor<T> {
+    fn visit_assert(
+        &mut self,
+        keyword: &Token,
+        condition: &Expr,
+        message: &Option<Expr>,
+        source: &str,
+    ) -> T;
     fn visit_block(&mut self, statements: &[Stmt]) -> T;
+    fn visit_destructure(&mut self, pattern: &Pattern, initializer: &Expr) -> T;
     fn visit_expression(&mut self, value: &Expr) -> T;
-    fn visit_function(&mut self, name: &Token, params: &[Token], body: &[Stmt]) -> T;
+    fn visit_function(
+        &mut self,
+        name: &Token,
+        params: &[Token],
+        param_types: &[Option<Token>],
+        return_type: &Option<Token>,
+        body: &[Stmt],
+        doc: &Option<String>,
+    ) -> T;
     fn visit_if(
         &mut self,
         condition: &Expr,
         then_branch: &Stmt,
         else_branch: &Option<Box<Stmt>>,
     ) -> T;
+    fn visit_multi_assign(&mut self, targets: &[Token], values: &[Expr]) -> T;
     fn visit_print(&mut self, value: &Expr) -> T;
     fn visit_return(&mut self, keyword: &Token, value: &Option<Expr>) -> T;
-    fn visit_var(&mut self, name: &Token, initializer: &Option<Expr>) -> T;
+    fn visit_spawn(
+        &mut self,
+        keyword: &Token,
+        callee: &Expr,
+        paren: &Token,
+        arguments: &[Expr],
+    ) -> T;
+    fn visit_var(
+        &mut self,
+        name: &Token,
+        type_annotation: &Option<Token>,
+        initializer: &Option<Expr>,
+    ) -> T;
     fn visit_while(&mut self, condition: &Expr, body: &Stmt) -> T;
 }
@@ -0,0 +1,82 @@
+// Picks the candidate closest to a misspelled `name`. Only close matches count: a third of the
+// name may differ, otherwise the suggestion is more confusing than helpful.
+pub fn similar_name<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let max_distance = (name.chars().count() / 3).max(1);
+
+    candidates
+        .into_iter()
+        .filter(|&candidate| candidate != name)
+        .map(|candidate| {
+            // differing only in case is the most likely typo of all
+            let distance = if candidate.eq_ignore_ascii_case(name) {
+                0
+            } else {
+                edit_distance(name, candidate)
+            };
+
+            (distance, candidate)
+        })
+        .filter(|&(distance, _)| distance <= max_distance)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+// Levenshtein distance counting an adjacent swap as one edit, so `lenght` is close to `length`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+
+    // rows for the previous two prefixes of `a` and the current one
+    let mut before = vec![0; b.len() + 1];
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    let mut current = vec![0; b.len() + 1];
+
+    for i in 1..=a.len() {
+        current[0] = i;
+
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+
+            current[j] = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+
+        before.clone_from(&previous);
+        previous.clone_from(&current);
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod test {
+    use super::{edit_distance, similar_name};
+
+    #[test]
+    fn distances() {
+        assert_eq!(edit_distance("print", "print"), 0);
+        assert_eq!(edit_distance("pritn", "print"), 1);
+        assert_eq!(edit_distance("lenght", "length"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn picks_closest_candidate() {
+        let names = ["count", "counter", "total", "Total"];
+
+        assert_eq!(similar_name("cuont", names), Some("count"));
+        assert_eq!(similar_name("countre", names), Some("counter"));
+        assert_eq!(similar_name("TOTAL", names), Some("Total"));
+        assert_eq!(similar_name("x", names), None);
+        assert_eq!(similar_name("average", names), None);
+    }
+}
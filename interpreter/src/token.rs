@@ -1,3 +1,8 @@
+use num_bigint::BigInt;
+use rust_decimal::Decimal;
+
+use crate::span::Span;
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum TokenType {
     // Single-character tokens
@@ -9,8 +14,14 @@ pub enum TokenType {
     LeftBrace,
     // }
     RightBrace,
+    // [
+    LeftBracket,
+    // ]
+    RightBracket,
     // ,
     Comma,
+    // :
+    Colon,
     // .
     Dot,
     // -
@@ -46,9 +57,13 @@ pub enum TokenType {
     Identifier,
     String { literal: String },
     Number { literal: f64 },
+    Integer { literal: i64 },
+    BigInteger { literal: BigInt },
+    Decimal { literal: Decimal },
 
     // Keywords.
     And,
+    Assert,
     Class,
     Else,
     False,
@@ -59,6 +74,7 @@ pub enum TokenType {
     Or,
     Print,
     Return,
+    Spawn,
     Super,
     This,
     True,
@@ -75,4 +91,23 @@ pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
     pub line: i32,
+    // in chars, starting from 1
+    pub column: usize,
+    // in bytes from the start of source
+    pub offset: usize,
+    // of the lexeme in bytes
+    pub length: usize,
+    // `///` comments directly preceding this token
+    pub doc: Option<String>,
+}
+
+impl Token {
+    pub fn span(&self) -> Span {
+        Span {
+            offset: self.offset,
+            length: self.length,
+            line: self.line,
+            column: self.column,
+        }
+    }
 }
@@ -0,0 +1,717 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::codes::{self, ErrorCode};
+use crate::diagnostic::{Diagnostic, Diagnostics};
+use crate::expression::{self, Expr};
+use crate::object::Object;
+use crate::span::{Label, Span};
+use crate::statement::{self, Pattern, Stmt};
+use crate::token::{Token, TokenType};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Type {
+    Any,
+    Nil,
+    Bool,
+    // any of the numeric types below
+    Number,
+    Int,
+    Float,
+    Decimal,
+    String,
+    List,
+    Channel,
+    Future,
+    Function {
+        // None for the `Function` annotation, which accepts any signature
+        params: Option<Vec<Type>>,
+        ret: Box<Type>,
+        // the name in the declaration, for pointing at it from calls
+        defined: Option<Span>,
+    },
+}
+
+impl Type {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "Any" => Type::Any,
+            "Nil" => Type::Nil,
+            "Bool" => Type::Bool,
+            "Number" => Type::Number,
+            "Int" => Type::Int,
+            "Float" => Type::Float,
+            "Decimal" => Type::Decimal,
+            "String" => Type::String,
+            "List" => Type::List,
+            "Channel" => Type::Channel,
+            "Future" => Type::Future,
+            "Function" => Type::Function {
+                params: None,
+                ret: Box::new(Type::Any),
+                defined: None,
+            },
+            _ => return None,
+        })
+    }
+
+    pub(crate) fn of_object(object: &Object) -> Self {
+        match object {
+            Object::Null => Type::Nil,
+            Object::Boolean(_) => Type::Bool,
+            Object::Number(_) => Type::Float,
+            Object::Int(_) | Object::BigInt(_) => Type::Int,
+            Object::Decimal(_) => Type::Decimal,
+            Object::String(_) => Type::String,
+            Object::List(_) => Type::List,
+            Object::Channel(_) => Type::Channel,
+            Object::Future(_) => Type::Future,
+            Object::Callable(_) => Type::Function {
+                params: None,
+                ret: Box::new(Type::Any),
+                defined: None,
+            },
+        }
+    }
+
+    fn is_numeric(&self) -> bool {
+        matches!(self, Type::Number | Type::Int | Type::Float | Type::Decimal)
+    }
+
+    // whether a value of type `value` can be stored in a slot of this type
+    pub fn accepts(&self, value: &Type) -> bool {
+        match (self, value) {
+            (Type::Any, _) | (_, Type::Any) => true,
+            (Type::Number, value) => value.is_numeric(),
+            (Type::Function { params: None, .. }, Type::Function { .. }) => true,
+            (expected, value) => expected == value,
+        }
+    }
+
+    // None for operands that never work together, decimals do not mix with floats
+    fn arithmetic(left: &Type, right: &Type) -> Option<Type> {
+        Some(match (left, right) {
+            (Type::Decimal, Type::Float) | (Type::Float, Type::Decimal) => return None,
+            (Type::Int, Type::Int) => Type::Int,
+            (Type::Float, Type::Float | Type::Int) | (Type::Int, Type::Float) => Type::Float,
+            (Type::Decimal, Type::Decimal | Type::Int) | (Type::Int, Type::Decimal) => {
+                Type::Decimal
+            }
+            // a Number could turn out to be either
+            (left, right) if left.is_numeric() && right.is_numeric() => Type::Number,
+            _ => Type::Any,
+        })
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Any => write!(f, "Any"),
+            Type::Nil => write!(f, "Nil"),
+            Type::Bool => write!(f, "Bool"),
+            Type::Number => write!(f, "Number"),
+            Type::Int => write!(f, "Int"),
+            Type::Float => write!(f, "Float"),
+            Type::Decimal => write!(f, "Decimal"),
+            Type::String => write!(f, "String"),
+            Type::List => write!(f, "List"),
+            Type::Channel => write!(f, "Channel"),
+            Type::Future => write!(f, "Future"),
+            Type::Function { .. } => write!(f, "Function"),
+        }
+    }
+}
+
+struct Binding {
+    // what the name was annotated with, checked on every assignment
+    declared: Type,
+    // what is known about the current value, only functions are tracked
+    known: Type,
+}
+
+// Checks annotated code before it runs. Anything without annotations is `Any` and stays dynamic,
+// so only values flowing into annotated variables, parameters and returns are verified.
+pub struct TypeChecker {
+    scopes: Vec<HashMap<String, Binding>>,
+    return_types: Vec<Type>,
+    errors: Vec<Diagnostic>,
+}
+
+impl TypeChecker {
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![HashMap::new()],
+            return_types: vec![],
+            errors: vec![],
+        }
+    }
+
+    pub fn check(&mut self, statements: &[Stmt], diagnostics: &mut Diagnostics) {
+        for statement in statements {
+            statement.accept(self);
+        }
+
+        for error in self.errors.drain(..) {
+            diagnostics.push(error);
+        }
+    }
+
+    /// Type of a single expression as far as annotations tell, Any when nothing is known
+    pub fn infer(&mut self, expr: &Expr, diagnostics: &mut Diagnostics) -> Type {
+        let inferred = self.check_expr(expr);
+
+        for error in self.errors.drain(..) {
+            diagnostics.push(error);
+        }
+
+        inferred
+    }
+
+    fn error(&mut self, token: &Token, code: &'static ErrorCode, message: String) {
+        self.errors
+            .push(Diagnostic::error(Some(token.span()), message).with_code(code));
+    }
+
+    fn define(&mut self, name: &str, declared: Type, known: Type) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_owned(), Binding { declared, known });
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Binding> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    fn lookup_mut(&mut self, name: &str) -> Option<&mut Binding> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+    }
+
+    fn annotation(&mut self, annotation: &Option<Token>) -> Type {
+        match annotation {
+            None => Type::Any,
+            Some(token) => Type::from_name(&token.lexeme).unwrap_or_else(|| {
+                self.error(
+                    token,
+                    codes::UNKNOWN_TYPE,
+                    format!("Unknown type '{}'.", token.lexeme),
+                );
+
+                Type::Any
+            }),
+        }
+    }
+
+    fn check_assignment(&mut self, name: &Token, value: &Type) {
+        let declared = match self.lookup_mut(&name.lexeme) {
+            Some(binding) => {
+                // the name could now hold anything
+                binding.known = binding.declared.clone();
+                binding.declared.clone()
+            }
+            None => return,
+        };
+
+        if !declared.accepts(value) {
+            self.error(
+                name,
+                codes::TYPE_MISMATCH,
+                format!(
+                    "Cannot assign {} to '{}' of type {}.",
+                    value, name.lexeme, declared
+                ),
+            );
+        }
+    }
+
+    fn mixed_operands(&mut self, operator: &Token, left: &Type, right: &Type) {
+        self.error(
+            operator,
+            codes::INVALID_OPERANDS,
+            format!("Cannot mix {} and {} operands.", left, right),
+        );
+    }
+
+    fn bind_pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Name(name) => self.define(&name.lexeme, Type::Any, Type::Any),
+            Pattern::List { elements, .. } => {
+                for element in elements {
+                    self.bind_pattern(element);
+                }
+            }
+            Pattern::Fields { names, .. } => {
+                for name in names {
+                    self.define(&name.lexeme, Type::Any, Type::Any);
+                }
+            }
+        }
+    }
+
+    fn check_expr(&mut self, expr: &Expr) -> Type {
+        expr.accept(self)
+    }
+
+    fn check_stmt(&mut self, stmt: &Stmt) {
+        stmt.accept(self)
+    }
+}
+
+impl expression::Visitor<Type> for TypeChecker {
+    fn visit_assign(&mut self, name: &Token, value: &Expr) -> Type {
+        let value = self.check_expr(value);
+
+        self.check_assignment(name, &value);
+
+        value
+    }
+
+    fn visit_binary(&mut self, left: &Expr, operator: &Token, right: &Expr) -> Type {
+        let left = self.check_expr(left);
+        let right = self.check_expr(right);
+
+        match operator.token_type {
+            TokenType::Plus if left == Type::String && right == Type::String => Type::String,
+            TokenType::Plus | TokenType::Minus | TokenType::Star | TokenType::Slash => {
+                Type::arithmetic(&left, &right).unwrap_or_else(|| {
+                    self.mixed_operands(operator, &left, &right);
+
+                    Type::Any
+                })
+            }
+            TokenType::Greater
+            | TokenType::GreaterEqual
+            | TokenType::Less
+            | TokenType::LessEqual => {
+                if Type::arithmetic(&left, &right).is_none() {
+                    self.mixed_operands(operator, &left, &right);
+                }
+
+                Type::Bool
+            }
+            _ => Type::Bool,
+        }
+    }
+
+    fn visit_call(&mut self, callee: &Expr, paren: &Token, arguments: &[Expr]) -> Type {
+        let callee = self.check_expr(callee);
+        let arguments = arguments
+            .iter()
+            .map(|argument| self.check_expr(argument))
+            .collect::<Vec<_>>();
+
+        match callee {
+            Type::Function {
+                params: Some(params),
+                ret,
+                defined,
+            } => {
+                if params.len() != arguments.len() {
+                    let labels = defined.map(|span| Label::new(span, "function defined here"));
+
+                    self.errors.push(Diagnostic {
+                        labels: labels.into_iter().collect(),
+                        ..Diagnostic::error(
+                            Some(paren.span()),
+                            format!(
+                                "Expected {} arguments but got {}.",
+                                params.len(),
+                                arguments.len()
+                            ),
+                        )
+                        .with_code(codes::ARITY_MISMATCH)
+                    });
+                }
+
+                for (i, (param, argument)) in params.iter().zip(arguments.iter()).enumerate() {
+                    if !param.accepts(argument) {
+                        self.error(
+                            paren,
+                            codes::TYPE_MISMATCH,
+                            format!(
+                                "Argument {} is {} but parameter expects {}.",
+                                i + 1,
+                                argument,
+                                param
+                            ),
+                        );
+                    }
+                }
+
+                *ret
+            }
+            _ => Type::Any,
+        }
+    }
+
+    fn visit_get(&mut self, object: &Expr, _name: &Token) -> Type {
+        self.check_expr(object);
+
+        Type::Any
+    }
+
+    fn visit_grouping(&mut self, expression: &Expr) -> Type {
+        self.check_expr(expression)
+    }
+
+    fn visit_list(&mut self, _bracket: &Token, elements: &[Expr]) -> Type {
+        for element in elements {
+            self.check_expr(element);
+        }
+
+        Type::List
+    }
+
+    fn visit_literal(&mut self, object: &Object) -> Type {
+        Type::of_object(object)
+    }
+
+    fn visit_logical(&mut self, left: &Expr, _operator: &Token, right: &Expr) -> Type {
+        let left = self.check_expr(left);
+        let right = self.check_expr(right);
+
+        if left == right {
+            left
+        } else {
+            Type::Any
+        }
+    }
+
+    fn visit_set(&mut self, object: &Expr, _token: &Token, value: &Expr) -> Type {
+        self.check_expr(object);
+        self.check_expr(value)
+    }
+
+    fn visit_super(&mut self, _keyword: &Token, _method: &Token) -> Type {
+        Type::Any
+    }
+
+    fn visit_this(&mut self, _keyword: &Token) -> Type {
+        Type::Any
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Expr) -> Type {
+        let right = self.check_expr(right);
+
+        match operator.token_type {
+            TokenType::Bang => Type::Bool,
+            _ if right.is_numeric() => right,
+            _ => Type::Any,
+        }
+    }
+
+    fn visit_variable(&mut self, name: &Token) -> Type {
+        self.lookup(&name.lexeme)
+            .map(|binding| binding.known.clone())
+            .unwrap_or(Type::Any)
+    }
+}
+
+impl statement::Visitor<()> for TypeChecker {
+    fn visit_assert(
+        &mut self,
+        _keyword: &Token,
+        condition: &Expr,
+        message: &Option<Expr>,
+        _source: &str,
+    ) {
+        self.check_expr(condition);
+
+        if let Some(message) = message {
+            self.check_expr(message);
+        }
+    }
+
+    fn visit_block(&mut self, statements: &[Stmt]) {
+        self.scopes.push(HashMap::new());
+
+        for statement in statements {
+            self.check_stmt(statement);
+        }
+
+        self.scopes.pop();
+    }
+
+    fn visit_destructure(&mut self, pattern: &Pattern, initializer: &Expr) {
+        self.check_expr(initializer);
+        self.bind_pattern(pattern);
+    }
+
+    fn visit_expression(&mut self, value: &Expr) {
+        self.check_expr(value);
+    }
+
+    fn visit_function(
+        &mut self,
+        name: &Token,
+        params: &[Token],
+        param_types: &[Option<Token>],
+        return_type: &Option<Token>,
+        body: &[Stmt],
+        _doc: &Option<String>,
+    ) {
+        let param_types = param_types
+            .iter()
+            .map(|annotation| self.annotation(annotation))
+            .collect::<Vec<_>>();
+        let return_type = self.annotation(return_type);
+
+        // defined before the body to allow recursion
+        self.define(
+            &name.lexeme,
+            Type::Any,
+            Type::Function {
+                params: Some(param_types.clone()),
+                ret: Box::new(return_type.clone()),
+                defined: Some(name.span()),
+            },
+        );
+
+        // falling off the end returns nil
+        if !return_type.accepts(&Type::Nil) && !always_returns(body) {
+            self.error(
+                name,
+                codes::MISSING_RETURN,
+                format!(
+                    "Function '{}' can end without returning {}.",
+                    name.lexeme, return_type
+                ),
+            );
+        }
+
+        self.scopes.push(HashMap::new());
+        self.return_types.push(return_type);
+
+        for (param, param_type) in params.iter().zip(param_types) {
+            self.define(&param.lexeme, param_type.clone(), param_type);
+        }
+
+        for statement in body {
+            self.check_stmt(statement);
+        }
+
+        self.return_types.pop();
+        self.scopes.pop();
+    }
+
+    fn visit_if(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: &Option<Box<Stmt>>) {
+        self.check_expr(condition);
+        self.check_stmt(then_branch);
+
+        if let Some(else_branch) = else_branch {
+            self.check_stmt(else_branch);
+        }
+    }
+
+    fn visit_multi_assign(&mut self, targets: &[Token], values: &[Expr]) {
+        let values = values
+            .iter()
+            .map(|value| self.check_expr(value))
+            .collect::<Vec<_>>();
+
+        if values.len() == targets.len() {
+            for (target, value) in targets.iter().zip(values.iter()) {
+                self.check_assignment(target, value);
+            }
+        } else {
+            for target in targets {
+                self.check_assignment(target, &Type::Any);
+            }
+        }
+    }
+
+    fn visit_print(&mut self, value: &Expr) {
+        self.check_expr(value);
+    }
+
+    fn visit_spawn(&mut self, _keyword: &Token, callee: &Expr, paren: &Token, arguments: &[Expr]) {
+        expression::Visitor::visit_call(self, callee, paren, arguments);
+    }
+
+    fn visit_return(&mut self, keyword: &Token, value: &Option<Expr>) {
+        let value = match value {
+            Some(value) => self.check_expr(value),
+            None => Type::Nil,
+        };
+
+        if let Some(expected) = self.return_types.last() {
+            if !expected.accepts(&value) {
+                let message = format!(
+                    "Cannot return {} from function returning {}.",
+                    value, expected
+                );
+
+                self.error(keyword, codes::TYPE_MISMATCH, message);
+            }
+        }
+    }
+
+    fn visit_var(
+        &mut self,
+        name: &Token,
+        type_annotation: &Option<Token>,
+        initializer: &Option<Expr>,
+    ) {
+        let declared = self.annotation(type_annotation);
+
+        // without an initializer the annotation only constrains later assignments
+        if let Some(initializer) = initializer {
+            let value = self.check_expr(initializer);
+
+            if !declared.accepts(&value) {
+                self.error(
+                    name,
+                    codes::TYPE_MISMATCH,
+                    format!(
+                        "Cannot initialize '{}' of type {} with {}.",
+                        name.lexeme, declared, value
+                    ),
+                );
+            }
+        }
+
+        self.define(&name.lexeme, declared.clone(), declared);
+    }
+
+    fn visit_while(&mut self, condition: &Expr, body: &Stmt) {
+        self.check_expr(condition);
+        self.check_stmt(body);
+    }
+}
+
+// whether running `statements` reaches a `return` on every path, loops are assumed to be left
+fn always_returns(statements: &[Stmt]) -> bool {
+    statements.iter().any(|statement| match statement {
+        Stmt::Return { .. } => true,
+        Stmt::Block { statements } => always_returns(statements),
+        Stmt::If {
+            then_branch,
+            else_branch: Some(else_branch),
+            ..
+        } => {
+            always_returns(std::slice::from_ref(then_branch))
+                && always_returns(std::slice::from_ref(else_branch))
+        }
+        _ => false,
+    })
+}
+
+impl Default for TypeChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TypeChecker;
+
+    use crate::diagnostic::Diagnostics;
+    use crate::parser::parse_source;
+
+    fn check(source: &str) -> Vec<String> {
+        let statements = parse_source(source, &mut Diagnostics::default());
+
+        let mut diagnostics = Diagnostics::default();
+        TypeChecker::new().check(&statements, &mut diagnostics);
+
+        diagnostics
+            .drain()
+            .map(|diagnostic| diagnostic.message)
+            .collect()
+    }
+
+    #[test]
+    fn unannotated_code_is_dynamic() {
+        assert!(check("var a = 1; a = \"s\"; fun f(x) { return x; } f(1); f = nil;").is_empty());
+    }
+
+    #[test]
+    fn annotation_mismatches() {
+        assert_eq!(
+            check("var x: Number = \"s\"; var y: Int = 1; y = 1.5;"),
+            vec![
+                "Cannot initialize 'x' of type Number with String.",
+                "Cannot assign Float to 'y' of type Int.",
+            ]
+        );
+    }
+
+    #[test]
+    fn declarations_without_initializer() {
+        assert_eq!(
+            check("var x: Int; x = 1; x = \"s\";"),
+            vec!["Cannot assign String to 'x' of type Int."]
+        );
+    }
+
+    #[test]
+    fn decimals_do_not_mix_with_floats() {
+        assert_eq!(
+            check(
+                "var a = 1.5d + 1.5; var b: Decimal = 1.5d * 2; var c: Float = 2 / 1.5; \
+                 var d = 1.5 < 2d; var e = 1.5 == 1.5d;"
+            ),
+            vec![
+                "Cannot mix Decimal and Float operands.",
+                "Cannot mix Float and Decimal operands.",
+            ]
+        );
+    }
+
+    #[test]
+    fn annotated_returns_cannot_be_skipped() {
+        assert_eq!(
+            check(
+                "fun f(): Int { print 1; }
+                 fun g(n): Int { if (n) return 1; }
+                 fun h(n): Int { if (n) { return 1; } else return 2; }
+                 fun i(): Nil { }
+                 fun k(): Any { }
+                 fun j() { }"
+            ),
+            vec![
+                "Function 'f' can end without returning Int.",
+                "Function 'g' can end without returning Int.",
+            ]
+        );
+    }
+
+    #[test]
+    fn function_signatures() {
+        assert_eq!(
+            check(
+                "fun f(a: String): Bool { return 1; } var b: Bool = f(2); var c: Int = f(\"s\");"
+            ),
+            vec![
+                "Cannot return Int from function returning Bool.",
+                "Argument 1 is Int but parameter expects String.",
+                "Cannot initialize 'c' of type Int with Bool.",
+            ]
+        );
+    }
+
+    #[test]
+    fn arity_mismatch_points_at_definition() {
+        let statements = parse_source("fun f(a) {}\nf();", &mut Diagnostics::default());
+
+        let mut diagnostics = Diagnostics::default();
+        TypeChecker::new().check(&statements, &mut diagnostics);
+
+        let errors = diagnostics.drain().collect::<Vec<_>>();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "Expected 1 arguments but got 0.");
+        assert_eq!(
+            errors[0]
+                .labels
+                .iter()
+                .map(|label| (label.span.line, label.span.column, label.message.as_str()))
+                .collect::<Vec<_>>(),
+            [(1, 5, "function defined here")]
+        );
+    }
+}